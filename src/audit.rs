@@ -0,0 +1,250 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::{fs::File, io::AsyncWriteExt, sync::Mutex};
+
+use crate::batch::Outcome;
+use crate::ctfd::SolveTracker;
+use crate::engine::{CompiledScript, LocalCalls, UserInput};
+
+/// Submitted payload as stored in the log, tagged with its kind
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "content", rename_all = "lowercase")]
+pub enum AuditInput {
+    Text(String),
+    Json(serde_json::Value),
+    /// Hex encoded raw body
+    Bytes(String),
+}
+
+impl AuditInput {
+    pub fn from_user_input(input: &UserInput) -> Self {
+        match input {
+            UserInput::Text(text) => AuditInput::Text(text.clone()),
+            UserInput::Structured(value) => AuditInput::Json(value.clone()),
+            UserInput::Bytes(bytes) => AuditInput::Bytes(hex::encode(bytes)),
+        }
+    }
+
+    pub fn to_user_input(&self) -> Result<UserInput> {
+        Ok(match self {
+            AuditInput::Text(text) => UserInput::Text(text.clone()),
+            AuditInput::Json(value) => UserInput::Structured(value.clone()),
+            AuditInput::Bytes(encoded) => UserInput::Bytes(
+                hex::decode(encoded).map_err(|e| anyhow!("Invalid logged bytes: {}", e))?,
+            ),
+        })
+    }
+
+    /// SHA-256 of the payload as the script saw it, for spotting repeated submissions
+    fn sha256(&self) -> String {
+        let mut hasher = Sha256::new();
+        match self {
+            AuditInput::Text(text) => hasher.update(text.as_bytes()),
+            AuditInput::Json(value) => hasher.update(value.to_string().as_bytes()),
+            AuditInput::Bytes(encoded) => hasher.update(hex::decode(encoded).unwrap_or_default()),
+        }
+        hex::encode(hasher.finalize())
+    }
+}
+
+/// One `/api/submit` call
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// Milliseconds since the Unix epoch
+    pub timestamp: u64,
+    pub team: Option<String>,
+    pub ip: Option<String>,
+    pub challenge: String,
+    pub input_sha256: String,
+    pub input: AuditInput,
+    pub verdict: Outcome,
+    pub output: String,
+    pub duration_ms: u128,
+    pub sandbox_id: String,
+}
+
+impl AuditEntry {
+    pub fn new(
+        challenge: &str,
+        input: &UserInput,
+        result: &Result<Result<String, String>>,
+        duration_ms: u128,
+        sandbox_id: &str,
+    ) -> Self {
+        let input = AuditInput::from_user_input(input);
+        let (verdict, output) = match result {
+            Ok(Ok(output)) => (Outcome::Ok, output.clone()),
+            Ok(Err(output)) => (Outcome::Err, output.clone()),
+            Err(err) => (Outcome::Error, err.to_string()),
+        };
+
+        Self {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or(0),
+            team: None,
+            ip: None,
+            challenge: challenge.to_string(),
+            input_sha256: input.sha256(),
+            input,
+            verdict,
+            output,
+            duration_ms,
+            sandbox_id: sandbox_id.to_string(),
+        }
+    }
+}
+
+/// Append-only JSONL log of submissions
+pub struct AuditLog {
+    file: Mutex<File>,
+}
+
+impl AuditLog {
+    pub async fn open(path: &Path) -> Result<Self> {
+        let file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await
+            .map_err(|e| anyhow!("Failed to open audit log {}: {}", path.display(), e))?;
+
+        Ok(Self {
+            file: Mutex::new(file),
+        })
+    }
+
+    pub async fn record(&self, entry: &AuditEntry) -> Result<()> {
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+
+        // One write per entry keeps lines whole even if the process dies mid-log
+        let mut file = self.file.lock().await;
+        file.write_all(line.as_bytes()).await?;
+        file.flush().await?;
+        Ok(())
+    }
+}
+
+/// Read every entry of an audit log, in order
+pub fn read_log(path: &Path) -> Result<Vec<AuditEntry>> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| anyhow!("Failed to read audit log {}: {}", path.display(), e))?;
+
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            serde_json::from_str(line)
+                .map_err(|e| anyhow!("{}:{}: invalid entry: {}", path.display(), index + 1, e))
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Change {
+    Unchanged,
+    /// Same verdict, different output
+    Output,
+    /// Ok before, Err or a runtime error now
+    Regression,
+    /// Err or a runtime error before, Ok now
+    Fixed,
+    /// Recognised as a solve now but not when it was submitted
+    NewSolve,
+    /// A solve when submitted but not anymore
+    LostSolve,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReplayResult {
+    pub timestamp: u64,
+    pub team: Option<String>,
+    pub sandbox_id: String,
+    pub change: Change,
+    pub logged: Outcome,
+    pub verdict: Outcome,
+    pub logged_output: String,
+    pub output: String,
+    pub duration_ms: u128,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ReplaySummary {
+    pub total: usize,
+    pub unchanged: usize,
+    pub changed: usize,
+    pub regressions: usize,
+    pub new_solves: usize,
+}
+
+impl ReplaySummary {
+    pub fn add(&mut self, result: &ReplayResult) {
+        self.total += 1;
+        match result.change {
+            Change::Unchanged => self.unchanged += 1,
+            Change::Regression => {
+                self.changed += 1;
+                self.regressions += 1;
+            }
+            Change::NewSolve => {
+                self.changed += 1;
+                self.new_solves += 1;
+            }
+            _ => self.changed += 1,
+        }
+    }
+}
+
+/// Re-run a logged submission against the current script and classify the difference.
+///
+/// Uploaded files are not kept, so multipart submissions replay in an empty sandbox.
+pub fn replay(
+    script: &CompiledScript,
+    calls: &LocalCalls,
+    entry: &AuditEntry,
+    solves: &SolveTracker,
+) -> ReplayResult {
+    let started = std::time::Instant::now();
+    let result = entry.input.to_user_input().and_then(|input| {
+        calls.run(entry.team.clone(), |call| {
+            script.call_check_with(&input, call)
+        })
+    });
+    let (verdict, output) = match result {
+        Ok(Ok(output)) => (Outcome::Ok, output),
+        Ok(Err(output)) => (Outcome::Err, output),
+        Err(err) => (Outcome::Error, err.to_string()),
+    };
+
+    let was_solve = entry.verdict == Outcome::Ok && solves.is_solve(&entry.output);
+    let is_solve = verdict == Outcome::Ok && solves.is_solve(&output);
+    let change = match (entry.verdict, verdict) {
+        _ if is_solve && !was_solve => Change::NewSolve,
+        _ if was_solve && !is_solve => Change::LostSolve,
+        (Outcome::Ok, Outcome::Err | Outcome::Error) => Change::Regression,
+        (Outcome::Err | Outcome::Error, Outcome::Ok) => Change::Fixed,
+        _ if entry.output != output => Change::Output,
+        _ => Change::Unchanged,
+    };
+
+    ReplayResult {
+        timestamp: entry.timestamp,
+        team: entry.team.clone(),
+        sandbox_id: entry.sandbox_id.clone(),
+        change,
+        logged: entry.verdict,
+        verdict,
+        logged_output: entry.output.clone(),
+        output,
+        duration_ms: started.elapsed().as_millis(),
+    }
+}
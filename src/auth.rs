@@ -0,0 +1,285 @@
+use anyhow::{anyhow, Result};
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::ctfd::CtfdClient;
+
+type HmacSha256 = Hmac<Sha256>;
+
+const SESSION_COOKIE: &str = "jailbox_session";
+
+/// An authenticated player, carried in signed session cookies
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Account {
+    /// Stable identifier that solves are deduplicated on
+    pub team: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// CTFd user and team ids, when verified against CTFd
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub team_id: Option<u64>,
+}
+
+impl Account {
+    fn named(team: &str) -> Self {
+        Self {
+            team: team.to_string(),
+            name: None,
+            user_id: None,
+            team_id: None,
+        }
+    }
+}
+
+/// Who is making a request, attached to every API request by [`authenticate`]
+#[derive(Clone, Debug, Default)]
+pub struct Identity {
+    pub account: Option<Account>,
+    pub admin: bool,
+}
+
+impl Identity {
+    pub fn team(&self) -> Option<&str> {
+        self.account.as_ref().map(|account| account.team.as_str())
+    }
+}
+
+/// Where player tokens are verified
+pub enum TeamTokens {
+    /// `team:token` pairs loaded from a file, keyed by token
+    Static(HashMap<String, String>),
+    /// CTFd access tokens, checked against the CTFd API
+    Ctfd(Arc<CtfdClient>),
+}
+
+impl TeamTokens {
+    /// Load a tokens file with one `team:token` pair per line; `#` starts a comment
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("Failed to read tokens file {}: {}", path.display(), e))?;
+
+        let mut tokens = HashMap::new();
+        for (index, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (team, token) = line.split_once(':').ok_or_else(|| {
+                anyhow!("{}:{}: expected `team:token`", path.display(), index + 1)
+            })?;
+            tokens.insert(token.trim().to_string(), team.trim().to_string());
+        }
+
+        Ok(TeamTokens::Static(tokens))
+    }
+
+    /// Resolve a player token to its account
+    pub async fn verify(&self, token: &str) -> Result<Option<Account>> {
+        match self {
+            TeamTokens::Static(tokens) => Ok(tokens
+                .iter()
+                .find(|(known, _)| constant_time_eq(known.as_bytes(), token.as_bytes()))
+                .map(|(_, team)| Account::named(team))),
+            TeamTokens::Ctfd(client) => client.verify(token).await,
+        }
+    }
+}
+
+pub struct AuthState {
+    pub team_tokens: Option<TeamTokens>,
+    pub session_secret: Option<Vec<u8>>,
+    pub session_ttl: u64,
+    pub admin_token: Option<String>,
+}
+
+impl AuthState {
+    /// Anonymous players are allowed only when no player authentication is configured
+    fn requires_team(&self) -> bool {
+        self.team_tokens.is_some()
+    }
+
+    /// Sign a session cookie value of the form `hex(account json).expiry.hex(mac)`
+    fn sign_session(&self, account: &Account) -> Option<String> {
+        let secret = self.session_secret.as_ref()?;
+        let expiry = now() + self.session_ttl;
+        let claims = serde_json::to_vec(account).ok()?;
+        let payload = format!("{}.{}", hex::encode(claims), expiry);
+
+        let mut mac = HmacSha256::new_from_slice(secret).ok()?;
+        mac.update(payload.as_bytes());
+        let signature = hex::encode(mac.finalize().into_bytes());
+
+        Some(format!("{}.{}", payload, signature))
+    }
+
+    fn verify_session(&self, value: &str) -> Option<Account> {
+        let secret = self.session_secret.as_ref()?;
+        let (payload, signature) = value.rsplit_once('.')?;
+        let (claims, expiry) = payload.split_once('.')?;
+
+        let mut mac = HmacSha256::new_from_slice(secret).ok()?;
+        mac.update(payload.as_bytes());
+        mac.verify_slice(&hex::decode(signature).ok()?).ok()?;
+
+        if expiry.parse::<u64>().ok()? < now() {
+            return None;
+        }
+        serde_json::from_slice(&hex::decode(claims).ok()?).ok()
+    }
+
+    /// Checks the admin token, then the session cookie, and only then asks the team
+    /// tokens, which may mean a call to CTFd
+    async fn identify(&self, headers: &HeaderMap) -> Result<Identity> {
+        let token = bearer_token(headers);
+        if let (Some(token), Some(admin_token)) = (token, &self.admin_token) {
+            if constant_time_eq(admin_token.as_bytes(), token.as_bytes()) {
+                return Ok(Identity {
+                    account: None,
+                    admin: true,
+                });
+            }
+        }
+
+        if let Some(account) = session_cookie(headers).and_then(|value| self.verify_session(value))
+        {
+            return Ok(Identity {
+                account: Some(account),
+                admin: false,
+            });
+        }
+
+        if let (Some(token), Some(team_tokens)) = (token, &self.team_tokens) {
+            if let Some(account) = team_tokens.verify(token).await? {
+                return Ok(Identity {
+                    account: Some(account),
+                    admin: false,
+                });
+            }
+        }
+
+        Ok(Identity::default())
+    }
+}
+
+/// Middleware resolving the caller's identity and rejecting unauthenticated players
+pub async fn authenticate(
+    State(auth): State<Arc<AuthState>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let identity = match auth.identify(request.headers()).await {
+        Ok(identity) => identity,
+        Err(err) => return unauthorized(StatusCode::BAD_GATEWAY, &err.to_string()),
+    };
+
+    // Creating a session is how players trade a token for a cookie
+    let is_login = request.uri().path() == "/api/session";
+    if auth.requires_team() && identity.account.is_none() && !identity.admin && !is_login {
+        return unauthorized(StatusCode::UNAUTHORIZED, "Authentication required");
+    }
+
+    request.extensions_mut().insert(identity);
+    next.run(request).await
+}
+
+/// Require the admin token for a privileged route
+pub async fn require_admin(request: Request, next: Next) -> Response {
+    let admin = request
+        .extensions()
+        .get::<Identity>()
+        .is_some_and(|identity| identity.admin);
+    if !admin {
+        return unauthorized(StatusCode::FORBIDDEN, "Admin token required");
+    }
+    next.run(request).await
+}
+
+/// `POST /api/session`: exchange a team token (bearer or body) for a signed cookie
+pub async fn handle_login(
+    State(auth): State<Arc<AuthState>>,
+    headers: HeaderMap,
+    body: String,
+) -> Response {
+    let token = bearer_token(&headers).unwrap_or(body.trim()).to_string();
+
+    let account = match &auth.team_tokens {
+        Some(team_tokens) => match team_tokens.verify(&token).await {
+            Ok(Some(account)) => account,
+            Ok(None) => return unauthorized(StatusCode::UNAUTHORIZED, "Invalid team token"),
+            Err(err) => return unauthorized(StatusCode::BAD_GATEWAY, &err.to_string()),
+        },
+        None => return unauthorized(StatusCode::NOT_FOUND, "Team tokens are not configured"),
+    };
+
+    let Some(session) = auth.sign_session(&account) else {
+        return unauthorized(StatusCode::NOT_FOUND, "Sessions are not configured");
+    };
+    let cookie = format!(
+        "{}={}; Path=/; HttpOnly; Secure; SameSite=Strict; Max-Age={}",
+        SESSION_COOKIE, session, auth.session_ttl
+    );
+
+    (
+        [(header::SET_COOKIE, cookie)],
+        Json(serde_json::json!({ "team": account.team, "name": account.name })),
+    )
+        .into_response()
+}
+
+/// `DELETE /api/session`: clear the session cookie
+pub async fn handle_logout() -> Response {
+    let cookie = format!(
+        "{}=; Path=/; HttpOnly; Secure; SameSite=Strict; Max-Age=0",
+        SESSION_COOKIE
+    );
+    ([(header::SET_COOKIE, cookie)], StatusCode::NO_CONTENT).into_response()
+}
+
+fn unauthorized(status: StatusCode, message: &str) -> Response {
+    (status, Json(serde_json::json!({ "error": message }))).into_response()
+}
+
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .map(str::trim)
+}
+
+fn session_cookie(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .find_map(|pair| pair.trim().strip_prefix(&format!("{}=", SESSION_COOKIE)))
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
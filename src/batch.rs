@@ -0,0 +1,156 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use crate::engine::{CompiledScript, LocalCalls, UserInput};
+
+/// One payload to feed to `check`
+pub struct BatchInput {
+    pub name: String,
+    pub content: String,
+    /// Set when the source bytes were not valid UTF-8 and had to be replaced
+    pub lossy: bool,
+}
+
+impl BatchInput {
+    /// Payload for `check`, parsing the content as JSON when requested
+    pub fn user_input(&self, json_input: bool) -> Result<UserInput> {
+        if !json_input {
+            return Ok(UserInput::Text(self.content.clone()));
+        }
+        serde_json::from_str(&self.content)
+            .map(UserInput::Structured)
+            .map_err(|e| anyhow!("Invalid JSON input {}: {}", self.name, e))
+    }
+
+    fn from_bytes(name: String, bytes: Vec<u8>) -> Self {
+        match String::from_utf8(bytes) {
+            Ok(content) => Self {
+                name,
+                content,
+                lossy: false,
+            },
+            Err(e) => Self {
+                name,
+                content: String::from_utf8_lossy(e.as_bytes()).into_owned(),
+                lossy: true,
+            },
+        }
+    }
+}
+
+/// Collect inputs from every requested source, in command line order
+pub fn gather_inputs(
+    input: Option<String>,
+    input_files: &[PathBuf],
+    stdin: bool,
+    input_dir: Option<&Path>,
+) -> Result<Vec<BatchInput>> {
+    let mut inputs = Vec::new();
+
+    if let Some(content) = input {
+        inputs.push(BatchInput {
+            name: "<input>".to_string(),
+            content,
+            lossy: false,
+        });
+    }
+
+    for path in input_files {
+        let bytes = fs::read(path)
+            .map_err(|e| anyhow!("Failed to read input file {}: {}", path.display(), e))?;
+        inputs.push(BatchInput::from_bytes(path.display().to_string(), bytes));
+    }
+
+    if stdin {
+        let mut bytes = Vec::new();
+        std::io::stdin().read_to_end(&mut bytes)?;
+        inputs.push(BatchInput::from_bytes("<stdin>".to_string(), bytes));
+    }
+
+    if let Some(dir) = input_dir {
+        let mut paths = fs::read_dir(dir)
+            .map_err(|e| anyhow!("Failed to read input directory {}: {}", dir.display(), e))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.is_file())
+            .collect::<Vec<_>>();
+        paths.sort();
+
+        for path in paths {
+            let bytes = fs::read(&path)?;
+            inputs.push(BatchInput::from_bytes(path.display().to_string(), bytes));
+        }
+    }
+
+    Ok(inputs)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    /// `check` returned a success value
+    Ok,
+    /// `check` returned an error value
+    Err,
+    /// The script threw at runtime
+    Error,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BatchResult {
+    pub name: String,
+    pub outcome: Outcome,
+    pub output: String,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub lossy: bool,
+    pub duration_ms: u128,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct BatchSummary {
+    pub total: usize,
+    pub ok: usize,
+    pub err: usize,
+    pub error: usize,
+}
+
+impl BatchSummary {
+    pub fn add(&mut self, result: &BatchResult) {
+        self.total += 1;
+        match result.outcome {
+            Outcome::Ok => self.ok += 1,
+            Outcome::Err => self.err += 1,
+            Outcome::Error => self.error += 1,
+        }
+    }
+}
+
+/// Run a single input against an already compiled script, in a sandbox of its own
+pub fn run_input(
+    script: &CompiledScript,
+    calls: &LocalCalls,
+    input: &BatchInput,
+    json_input: bool,
+) -> BatchResult {
+    let started = std::time::Instant::now();
+    let result = input
+        .user_input(json_input)
+        .and_then(|user_input| calls.run(None, |call| script.call_check_with(&user_input, call)));
+    let (outcome, output) = match result {
+        Ok(Ok(output)) => (Outcome::Ok, output),
+        Ok(Err(output)) => (Outcome::Err, output),
+        Err(err) => (Outcome::Error, err.to_string()),
+    };
+
+    BatchResult {
+        name: input.name.clone(),
+        outcome,
+        output,
+        lossy: input.lossy,
+        duration_ms: started.elapsed().as_millis(),
+    }
+}
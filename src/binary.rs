@@ -0,0 +1,175 @@
+use cpp_demangle::DemangleOptions;
+use object::{Object, ObjectSection, ObjectSymbol, SectionKind, SymbolKind, SymbolSection};
+
+/// Files larger than this are refused rather than parsed
+pub const MAX_BINARY_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Symbols, imports and sections of an ELF file, read without running it
+#[derive(Debug)]
+pub struct Binary {
+    /// File size in bytes
+    pub size: u64,
+    pub symbols: Vec<Symbol>,
+    /// Functions and data resolved by the dynamic linker
+    pub imports: Vec<String>,
+    pub sections: Vec<Section>,
+    /// Contents of the sections that hold constants and initialised data
+    data: Vec<Vec<u8>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Symbol {
+    /// As stored, mangled for C++
+    pub name: String,
+    pub demangled: Option<String>,
+    /// `text`, `data`, `tls` or `unknown`
+    pub kind: &'static str,
+    /// Section the symbol is defined in, `None` when it's undefined
+    pub section: Option<String>,
+    pub address: u64,
+    pub size: u64,
+    pub global: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct Section {
+    pub name: String,
+    /// `text`, `data`, `rodata`, `bss`, `tls`, `debug` or `other`
+    pub kind: &'static str,
+    pub size: u64,
+}
+
+impl Binary {
+    pub fn parse(bytes: &[u8]) -> object::Result<Self> {
+        let file = object::File::parse(bytes)?;
+
+        // Stripped binaries only have the dynamic symbol table left
+        let mut symbols = read_symbols(&file, file.symbols());
+        if symbols.is_empty() {
+            symbols = read_symbols(&file, file.dynamic_symbols());
+        }
+
+        let mut imports: Vec<String> = file
+            .imports()?
+            .iter()
+            .map(|import| String::from_utf8_lossy(import.name()).into_owned())
+            .collect();
+        imports.sort();
+        imports.dedup();
+
+        let mut sections = Vec::new();
+        let mut data = Vec::new();
+        for section in file.sections() {
+            let kind = section_kind(section.kind());
+            if matches!(kind, "rodata" | "data") {
+                data.push(section.data()?.to_vec());
+            }
+            sections.push(Section {
+                name: section.name().unwrap_or_default().to_string(),
+                kind,
+                size: section.size(),
+            });
+        }
+
+        Ok(Self {
+            size: bytes.len() as u64,
+            symbols,
+            imports,
+            sections,
+            data,
+        })
+    }
+
+    /// Whether a symbol or import is named `name`, mangled, demangled or as a bare
+    /// function name without parameters
+    pub fn has_symbol(&self, name: &str) -> bool {
+        self.imports.iter().any(|import| import == name)
+            || self.symbols.iter().any(|symbol| {
+                symbol.name == name
+                    || symbol.demangled.as_deref() == Some(name)
+                    || demangle(&symbol.name, DemangleOptions::new().no_params()).as_deref()
+                        == Some(name)
+            })
+    }
+
+    /// Whether `text` appears in constant or initialised data
+    pub fn contains(&self, text: &[u8]) -> bool {
+        !text.is_empty()
+            && self
+                .data
+                .iter()
+                .any(|data| data.windows(text.len()).any(|window| window == text))
+    }
+
+    /// Runs of at least `min_len` printable ASCII characters in constant or initialised
+    /// data, like `strings`
+    pub fn strings(&self, min_len: usize) -> Vec<String> {
+        let min_len = min_len.max(1);
+        let mut strings = Vec::new();
+        for data in &self.data {
+            for run in data.split(|byte| !(byte.is_ascii_graphic() || *byte == b' ')) {
+                if run.len() >= min_len {
+                    strings.push(String::from_utf8_lossy(run).into_owned());
+                }
+            }
+        }
+        strings
+    }
+}
+
+fn read_symbols<'data>(
+    file: &object::File<'data>,
+    table: object::SymbolIterator<'data, '_>,
+) -> Vec<Symbol> {
+    table
+        .filter_map(|symbol| {
+            let name = symbol.name().ok().filter(|name| !name.is_empty())?;
+            if matches!(symbol.kind(), SymbolKind::Section | SymbolKind::File) {
+                return None;
+            }
+            let section = match symbol.section() {
+                SymbolSection::Section(index) => file
+                    .section_by_index(index)
+                    .ok()
+                    .and_then(|section| section.name().ok().map(str::to_string)),
+                _ => None,
+            };
+            Some(Symbol {
+                name: name.to_string(),
+                demangled: demangle(name, DemangleOptions::new()),
+                kind: match symbol.kind() {
+                    SymbolKind::Text => "text",
+                    SymbolKind::Data => "data",
+                    SymbolKind::Tls => "tls",
+                    _ => "unknown",
+                },
+                section,
+                address: symbol.address(),
+                size: symbol.size(),
+                global: symbol.is_global(),
+            })
+        })
+        .collect()
+}
+
+fn section_kind(kind: SectionKind) -> &'static str {
+    match kind {
+        SectionKind::Text => "text",
+        SectionKind::Data => "data",
+        SectionKind::ReadOnlyData
+        | SectionKind::ReadOnlyDataWithRel
+        | SectionKind::ReadOnlyString => "rodata",
+        SectionKind::UninitializedData | SectionKind::Common => "bss",
+        SectionKind::Tls | SectionKind::UninitializedTls | SectionKind::TlsVariables => "tls",
+        SectionKind::Debug | SectionKind::DebugString => "debug",
+        _ => "other",
+    }
+}
+
+/// Demangle a C++ symbol name, `None` for names that aren't mangled
+fn demangle(name: &str, options: DemangleOptions) -> Option<String> {
+    cpp_demangle::Symbol::new(name)
+        .ok()?
+        .demangle(&options)
+        .ok()
+}
@@ -1,4 +1,4 @@
-use clap::{Parser, Subcommand};
+use clap::{ArgGroup, Parser, Subcommand, ValueEnum};
 use std::path::PathBuf;
 
 #[derive(Parser)]
@@ -10,6 +10,76 @@ pub struct Args {
     pub command: Commands,
 }
 
+/// Authentication and CORS settings for `listen`
+#[derive(clap::Args)]
+pub struct AuthArgs {
+    /// File of `team:token` lines; when set, players must authenticate
+    #[arg(long, conflicts_with = "ctfd_url")]
+    pub tokens_file: Option<PathBuf>,
+
+    /// CTFd base URL; when set, players authenticate with CTFd access tokens
+    #[arg(long)]
+    pub ctfd_url: Option<String>,
+
+    /// CTFd admin API token used to record solves
+    #[arg(long, env = "JAILBOX_CTFD_SECRET", hide_env_values = true)]
+    pub ctfd_secret: Option<String>,
+
+    /// Secret used to sign session cookies
+    #[arg(long, env = "JAILBOX_SESSION_SECRET", hide_env_values = true)]
+    pub session_secret: Option<String>,
+
+    /// Session cookie lifetime in seconds
+    #[arg(long, default_value = "86400")]
+    pub session_ttl: u64,
+
+    /// Bearer token granting access to admin routes
+    #[arg(long, env = "JAILBOX_ADMIN_TOKEN", hide_env_values = true)]
+    pub admin_token: Option<String>,
+
+    /// Allowed CORS origin (repeatable, `*` allows any but without the session cookie);
+    /// no CORS headers when unset
+    #[arg(long)]
+    pub cors_origin: Vec<String>,
+}
+
+/// Sandbox lifecycle settings for `listen`
+#[derive(clap::Args)]
+pub struct SandboxArgs {
+    /// Seconds after which a sandbox is destroyed, with anything still running in it
+    #[arg(long, default_value = "600")]
+    pub sandbox_ttl: u64,
+
+    /// Directory holding sandboxes [default: <system temp dir>/jailbox-sandboxes]
+    #[arg(long)]
+    pub sandbox_root: Option<PathBuf>,
+
+    /// Disk quota per sandbox in bytes; processes that exceed it are killed
+    #[arg(long)]
+    pub sandbox_quota: Option<u64>,
+
+    /// Total bytes all sandboxes may use before submissions get "server busy"
+    #[arg(long)]
+    pub sandbox_disk_cap: Option<u64>,
+}
+
+/// Compile artifact cache settings for `listen`
+#[derive(clap::Args)]
+pub struct CompileArgs {
+    /// Directory for precompiled headers and cached objects [default: <system temp dir>/jailbox-cache]
+    #[arg(long)]
+    pub compile_cache: Option<PathBuf>,
+
+    /// Size limit of the object cache in bytes, 0 disables it
+    #[arg(long, default_value = "268435456")]
+    pub compile_cache_size: u64,
+
+    /// Check compilers and build precompiled headers on first use instead of at startup,
+    /// in the sandbox of the submission that needs them
+    #[arg(long, default_value = "false")]
+    pub lazy_toolchains: bool,
+}
+
 #[derive(Subcommand)]
 pub enum Commands {
     /// Start the web server
@@ -29,6 +99,32 @@ pub enum Commands {
         /// Rune script file path
         #[arg(short, long)]
         exec: Option<PathBuf>,
+
+        /// Include Rune compile diagnostics in API error responses, and the warnings of
+        /// successful calls in an `x-script-diagnostics` header (admin use)
+        #[arg(long, default_value = "false")]
+        expose_diagnostics: bool,
+
+        /// Append every submission to this JSONL audit log
+        #[arg(long)]
+        audit_log: Option<PathBuf>,
+
+        /// Serve `/metrics` on this address instead of behind the admin token
+        #[arg(long)]
+        metrics_addr: Option<String>,
+
+        /// Seconds to wait for running checks on SIGINT/SIGTERM before killing them
+        #[arg(long, default_value = "30")]
+        shutdown_timeout: u64,
+
+        #[command(flatten)]
+        sandbox: SandboxArgs,
+
+        #[command(flatten)]
+        compile: CompileArgs,
+
+        #[command(flatten)]
+        auth: AuthArgs,
     },
     /// Run the collect function and return results
     Collect {
@@ -45,14 +141,27 @@ pub enum Commands {
         parse: bool,
     },
     /// Run the check function and return results
+    #[command(group(ArgGroup::new("source").required(true).multiple(true)))]
     Check {
         /// Rune script file path
         #[arg(short, long)]
         exec: Option<PathBuf>,
 
         /// User input
-        #[arg(short, long, alias = "user-input")]
-        input: String,
+        #[arg(short, long, alias = "user-input", group = "source")]
+        input: Option<String>,
+
+        /// Read user input from a file (repeatable)
+        #[arg(long, group = "source")]
+        input_file: Vec<PathBuf>,
+
+        /// Read user input from stdin
+        #[arg(long, group = "source")]
+        stdin: bool,
+
+        /// Run every file in a directory as a separate input
+        #[arg(long, group = "source")]
+        input_dir: Option<PathBuf>,
 
         /// Data bucket directory path
         #[arg(short, long, default_value = "./bucket")]
@@ -61,5 +170,174 @@ pub enum Commands {
         /// Whether to parse JSON output
         #[arg(short = 'P', long, default_value = "false")]
         parse: bool,
+
+        /// Output format
+        #[arg(short, long, value_enum, default_value = "human")]
+        format: BatchFormat,
+
+        /// Parse each input as JSON and pass it to check as Rune objects
+        #[arg(long, default_value = "false")]
+        json_input: bool,
+    },
+    /// Call an action declared in the challenge config
+    Call {
+        /// Action name
+        name: String,
+
+        /// Rune script file path
+        #[arg(short, long)]
+        exec: Option<PathBuf>,
+
+        /// Action argument, parsed as JSON when possible (repeatable)
+        #[arg(short, long)]
+        arg: Vec<String>,
+
+        /// Data bucket directory path
+        #[arg(short, long, default_value = "./bucket")]
+        dir: PathBuf,
+
+        /// Whether to parse JSON output
+        #[arg(short = 'P', long, default_value = "false")]
+        parse: bool,
+    },
+    /// Validate a challenge bucket (exits non-zero when it fails)
+    Lint {
+        /// Rune script file path
+        #[arg(short, long)]
+        exec: Option<PathBuf>,
+
+        /// Data bucket directory path
+        #[arg(short, long, default_value = "./bucket")]
+        dir: PathBuf,
+
+        /// Output format
+        #[arg(short, long, value_enum, default_value = "human")]
+        format: OutputFormat,
+
+        /// Fail on warnings as well as errors
+        #[arg(long, default_value = "false")]
+        deny_warnings: bool,
+
+        /// Regex matching flags that must not be hard-coded in bucket files
+        #[arg(long, default_value = r"flag\{[^}]*\}")]
+        flag_pattern: String,
+    },
+    /// Run expected-verdict fixtures through the check function
+    Test {
+        /// Rune script file path
+        #[arg(short, long)]
+        exec: Option<PathBuf>,
+
+        /// Data bucket directory path
+        #[arg(short, long, default_value = "./bucket")]
+        dir: PathBuf,
+
+        /// Fixtures file path, relative to the bucket unless absolute
+        #[arg(long, default_value = "fixtures.json")]
+        fixtures: PathBuf,
+
+        /// Output format
+        #[arg(short, long, value_enum, default_value = "human")]
+        format: TestFormat,
     },
+    /// Mutate seed payloads to find inputs that bypass the check filter
+    Fuzz {
+        /// Rune script file path
+        #[arg(short, long)]
+        exec: Option<PathBuf>,
+
+        /// Data bucket directory path
+        #[arg(short, long, default_value = "./bucket")]
+        dir: PathBuf,
+
+        /// Directory of seed payloads, one per file
+        #[arg(short, long)]
+        corpus: Option<PathBuf>,
+
+        /// Characters mutations may insert (defaults to printable ASCII and newline)
+        #[arg(long)]
+        alphabet: Option<String>,
+
+        /// Characters never produced, mirroring the challenge filter
+        #[arg(short, long, default_value = "")]
+        blacklist: String,
+
+        /// Regex matching the flag in check output
+        #[arg(long, default_value = r"flag\{[^}]*\}")]
+        flag_pattern: String,
+
+        /// Additional regex marking a successful output (repeatable)
+        #[arg(short, long)]
+        marker: Vec<String>,
+
+        /// Number of mutated inputs to run
+        #[arg(short = 'n', long, default_value = "10000")]
+        iterations: usize,
+
+        /// Maximum payload length in characters
+        #[arg(long, default_value = "256")]
+        max_len: usize,
+
+        /// PRNG seed, for reproducible runs
+        #[arg(long)]
+        seed: Option<u64>,
+
+        /// Output format
+        #[arg(short, long, value_enum, default_value = "human")]
+        format: BatchFormat,
+    },
+    /// Re-run submissions from an audit log against the current script
+    Replay {
+        /// Audit log written by `listen --audit-log`
+        log: PathBuf,
+
+        /// Rune script file path
+        #[arg(short, long)]
+        exec: Option<PathBuf>,
+
+        /// Data bucket directory path
+        #[arg(short, long, default_value = "./bucket")]
+        dir: PathBuf,
+
+        /// Only replay submissions from this team
+        #[arg(long)]
+        team: Option<String>,
+
+        /// Also list submissions whose result did not change
+        #[arg(long, default_value = "false")]
+        all: bool,
+
+        /// Output format
+        #[arg(short, long, value_enum, default_value = "human")]
+        format: BatchFormat,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum TestFormat {
+    /// Human readable, colorized on a terminal
+    Human,
+    /// A single JSON document
+    Json,
+    /// JUnit XML, for CI test reports
+    Junit,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum OutputFormat {
+    /// Human readable, colorized on a terminal
+    Human,
+    /// A single JSON document
+    Json,
+}
+
+/// Output of commands producing one result per input, which can also be streamed
+#[derive(Clone, Copy, ValueEnum)]
+pub enum BatchFormat {
+    /// Human readable, colorized on a terminal
+    Human,
+    /// A single JSON document
+    Json,
+    /// One JSON document per line
+    Jsonl,
 }
@@ -0,0 +1,836 @@
+use anyhow::{anyhow, Result};
+use regex::Regex;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::{Arc, LazyLock, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::config::{ChallengeConfig, ToolchainProfile};
+use crate::metrics;
+use crate::sandbox::{self, SandboxHandle};
+
+/// Directory under the system temp dir that holds compile artifacts by default
+const CACHE_DIR: &str = "jailbox-cache";
+
+/// Names of the files a compile leaves in the sandbox
+pub const SOURCE_FILE: &str = "main.cpp";
+pub const OBJECT_FILE: &str = "main.o";
+pub const BINARY_FILE: &str = "main";
+pub const PREPROCESSED_FILE: &str = "main.ii";
+pub const AST_FILE: &str = "main.ast.json";
+
+/// AST dumps larger than this are refused rather than parsed
+const MAX_AST_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Header generated from a profile's precompiled header set
+const PCH_HEADER: &str = "jailbox-pch.hpp";
+
+/// Result of compiling a submission in its sandbox
+#[derive(Debug)]
+pub struct CompileOutput {
+    pub success: bool,
+    /// Compiler and linker diagnostics
+    pub stderr: Vec<u8>,
+    /// Linked executable relative to the sandbox, when compilation succeeded
+    pub binary: Option<PathBuf>,
+    /// The object file came from the cache
+    pub cached: bool,
+    pub timed_out: bool,
+}
+
+/// Result of running the preprocessor on a submission in its sandbox
+#[derive(Debug)]
+pub struct PreprocessOutput {
+    pub success: bool,
+    /// Preprocessed source, including line markers
+    pub expanded: String,
+    pub stderr: Vec<u8>,
+    pub timed_out: bool,
+}
+
+/// Result of dumping a submission's Clang AST in its sandbox
+#[derive(Debug)]
+pub struct AstOutput {
+    /// Clang found no errors; the dump is partial otherwise
+    pub success: bool,
+    /// `-ast-dump=json` output
+    pub json: Vec<u8>,
+    pub stderr: Vec<u8>,
+    pub timed_out: bool,
+}
+
+/// `file:line:col: severity: message`, as printed by both GCC and Clang. The text format
+/// is read rather than `-fdiagnostics-format=json`, which Clang lacks and which would
+/// replace the stderr scripts show and the redactor masks.
+static DIAGNOSTIC: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(.+?):(\d+):(\d+): (fatal error|error|warning|note): (.*)$").unwrap()
+});
+
+/// A located compiler message, lines and columns 1-based as the compiler reports them
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub severity: String,
+    pub message: String,
+}
+
+/// Pick the located messages out of compiler output, skipping excerpts and context lines
+pub fn parse_diagnostics(stderr: &str) -> Vec<Diagnostic> {
+    stderr
+        .lines()
+        .filter_map(|line| {
+            let captures = DIAGNOSTIC.captures(line)?;
+            Some(Diagnostic {
+                file: captures[1].to_string(),
+                line: captures[2].parse().ok()?,
+                column: captures[3].parse().ok()?,
+                severity: captures[4].to_string(),
+                message: captures[5].to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Every toolchain profile of the challenge, sharing one object cache
+#[derive(Debug)]
+pub struct Toolchains {
+    profiles: BTreeMap<String, Profile>,
+    default: String,
+    bucket: PathBuf,
+    pch_dir: PathBuf,
+    cache: Option<Arc<ObjectCache>>,
+    timeout: Duration,
+    /// Clang for AST dumps, or why it can't run
+    clang: std::result::Result<Clang, String>,
+}
+
+/// A declared profile, set up at startup by `setup_all`, or on first use when the server
+/// runs with `--lazy-toolchains`
+#[derive(Debug)]
+struct Profile {
+    program: String,
+    config: ToolchainProfile,
+    /// Only a successful setup is kept, so a failed one is retried on the next use
+    compiler: Mutex<Option<Arc<Compiler>>>,
+}
+
+/// Clang executable found at startup
+#[derive(Debug)]
+pub struct Clang {
+    pub program: String,
+    pub version: String,
+}
+
+impl Toolchains {
+    /// Default cache directory, under the system temp dir
+    pub fn default_cache_dir() -> PathBuf {
+        std::env::temp_dir().join(CACHE_DIR)
+    }
+
+    /// Read the declared profiles; compilers are checked and precompiled headers built by
+    /// `setup_all`, or else on first use. A `cache_bytes` of zero disables the object cache.
+    pub fn new(
+        config: &ChallengeConfig,
+        bucket: &Path,
+        cache_dir: &Path,
+        cache_bytes: u64,
+    ) -> Result<Self> {
+        let cache = match cache_bytes {
+            0 => None,
+            _ => Some(Arc::new(ObjectCache::new(
+                cache_dir.join("objects"),
+                cache_bytes,
+            )?)),
+        };
+
+        let profiles: BTreeMap<String, Profile> = config
+            .compile
+            .profiles()
+            .into_iter()
+            .map(|(name, profile)| {
+                let program = config.profile_compiler(&profile).to_string();
+                let profile = Profile {
+                    program,
+                    config: profile,
+                    compiler: Mutex::new(None),
+                };
+                (name, profile)
+            })
+            .collect();
+
+        let default = config.compile.default_profile.clone();
+        if !profiles.contains_key(&default) {
+            return Err(anyhow!(
+                "Default toolchain profile `{}` is not declared",
+                default
+            ));
+        }
+
+        let clang = compiler_version(&config.compile.clang)
+            .and_then(|version| {
+                check_clang_version(&version)?;
+                Ok(Clang {
+                    program: config.compile.clang.clone(),
+                    version,
+                })
+            })
+            .map_err(|e| e.to_string());
+
+        Ok(Self {
+            profiles,
+            default,
+            bucket: bucket.to_path_buf(),
+            pch_dir: cache_dir.join("pch"),
+            cache,
+            timeout: Duration::from_millis(config.compile.timeout_ms),
+            clang,
+        })
+    }
+
+    /// Clang for AST dumps; an error naming the reason when it isn't installed
+    pub fn clang(&self) -> io::Result<&Clang> {
+        self.clang.as_ref().map_err(|reason| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("Clang is not available for AST dumps: {}", reason),
+            )
+        })
+    }
+
+    /// Resolve a profile name, the default one when `name` is `None`
+    pub fn check(&self, name: Option<&str>) -> io::Result<&str> {
+        let name = name.unwrap_or(&self.default);
+        match self.profiles.get_key_value(name) {
+            Some((name, _)) => Ok(name),
+            None => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("Unknown toolchain profile: {}", name),
+            )),
+        }
+    }
+
+    /// Check every profile's compiler and build its precompiled headers in `sandbox`, so
+    /// a broken toolchain is reported before any submission needs it
+    pub fn setup_all(&self, sandbox: &SandboxHandle) -> Result<Vec<Arc<Compiler>>> {
+        self.profiles
+            .keys()
+            .map(|name| Ok(self.get(Some(name), sandbox)?))
+            .collect()
+    }
+
+    /// A profile's compiler, set up in `sandbox` if this is its first use. Other calls for
+    /// the same profile wait for that setup.
+    pub fn get(&self, name: Option<&str>, sandbox: &SandboxHandle) -> io::Result<Arc<Compiler>> {
+        let name = self.check(name)?;
+        let profile = &self.profiles[name];
+        let mut compiler = profile.compiler.lock().unwrap();
+        if let Some(compiler) = &*compiler {
+            return Ok(compiler.clone());
+        }
+
+        let built = Arc::new(
+            Compiler::new(self, name, profile, sandbox)
+                .map_err(|e| io::Error::other(format!("Toolchain profile `{}`: {}", name, e)))?,
+        );
+        *compiler = Some(built.clone());
+        Ok(built)
+    }
+
+    /// Declared profiles and their compiler executables
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &str)> {
+        self.profiles
+            .iter()
+            .map(|(name, profile)| (name, profile.program.as_str()))
+    }
+}
+
+/// One toolchain profile, ready to compile submissions
+#[derive(Debug)]
+pub struct Compiler {
+    name: String,
+    program: String,
+    /// Flags for compiling, with the standard, defines and include dirs spelled out
+    flags: Vec<String>,
+    /// Just the standard, defines and include dirs, which any compiler understands
+    language_flags: Vec<String>,
+    link_flags: Vec<String>,
+    profile: ToolchainProfile,
+    /// Header force-included into every compile, backed by its `.gch`
+    pch: Option<PathBuf>,
+    cache: Option<Arc<ObjectCache>>,
+    timeout: Duration,
+    /// Mixed into cache keys so a toolchain upgrade doesn't reuse old objects
+    version: String,
+}
+
+impl Compiler {
+    /// Check the profile's compiler and include dirs, and build its precompiled headers
+    /// in `sandbox`
+    fn new(
+        toolchains: &Toolchains,
+        name: &str,
+        declared: &Profile,
+        sandbox: &SandboxHandle,
+    ) -> Result<Self> {
+        let program = &declared.program;
+        let profile = &declared.config;
+        let bucket = &toolchains.bucket;
+        let version = compiler_version(program)?;
+
+        let mut language_flags = Vec::new();
+        if let Some(std) = &profile.std {
+            language_flags.push(format!("-std={}", std));
+        }
+        language_flags.extend(profile.defines.iter().map(|define| format!("-D{}", define)));
+        let bucket_root = bucket.canonicalize()?;
+        for dir in &profile.include_dirs {
+            let path = bucket
+                .join(dir)
+                .canonicalize()
+                .map_err(|e| anyhow!("Include dir {} is not usable: {}", dir, e))?;
+            if !path.is_dir() || !path.starts_with(&bucket_root) {
+                return Err(anyhow!(
+                    "Include dir {} is not a directory in the data bucket",
+                    dir
+                ));
+            }
+            language_flags.push(format!("-I{}", path.display()));
+        }
+
+        // Profile flags go right after the standard, as they always have
+        let mut flags = language_flags.clone();
+        let after_std = usize::from(profile.std.is_some());
+        flags.splice(after_std..after_std, profile.flags.iter().cloned());
+        if reports_display_columns(&version) {
+            flags.push("-fdiagnostics-column-unit=byte".to_string());
+        }
+
+        let mut compiler = Self {
+            name: name.to_string(),
+            program: program.to_string(),
+            flags,
+            language_flags,
+            link_flags: profile.link_flags.clone(),
+            profile: profile.clone(),
+            pch: None,
+            cache: toolchains.cache.clone(),
+            timeout: toolchains.timeout,
+            version,
+        };
+
+        if !profile.precompiled_headers.is_empty() {
+            compiler.pch = Some(compiler.build_pch(
+                sandbox,
+                &toolchains.pch_dir,
+                &profile.precompiled_headers,
+            )?);
+        }
+
+        Ok(compiler)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn program(&self) -> &str {
+        &self.program
+    }
+
+    pub fn pch(&self) -> Option<&Path> {
+        self.pch.as_deref()
+    }
+
+    /// Whether the compiler replaces trigraphs: with `-trigraphs`, or an ISO standard
+    /// before C++17. GCC's default GNU dialects leave them alone.
+    pub fn trigraphs(&self) -> bool {
+        let mut enabled = false;
+        for flag in &self.flags {
+            match flag.as_str() {
+                "-trigraphs" | "-ftrigraphs" | "-ansi" => enabled = true,
+                "-fno-trigraphs" => enabled = false,
+                flag => {
+                    if let Some(std) = flag.strip_prefix("-std=") {
+                        enabled = matches!(
+                            std,
+                            "c++98" | "c++03" | "c++0x" | "c++11" | "c++1y" | "c++14"
+                        );
+                    }
+                }
+            }
+        }
+        enabled
+    }
+
+    /// Refuse script-supplied flags the profile doesn't allow
+    fn check_flags(&self, extra_flags: &[String]) -> io::Result<()> {
+        match extra_flags
+            .iter()
+            .find(|flag| !self.profile.allows_flag(flag))
+        {
+            Some(flag) => Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!(
+                    "Flag {} is not allowed by toolchain profile `{}`",
+                    flag, self.name
+                ),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Hash of everything besides the source that affects the object file
+    fn toolchain_key(&self, hasher: &mut Sha256, extra_flags: &[String]) {
+        let pch = self
+            .pch
+            .as_ref()
+            .map(|pch| pch.to_string_lossy().to_string());
+        for part in [&self.program, &self.version]
+            .into_iter()
+            .chain(&self.flags)
+            .chain(extra_flags)
+            .chain(&pch)
+        {
+            hasher.update(part.as_bytes());
+            hasher.update([0]);
+        }
+    }
+
+    /// Generate and compile a header including `headers` in `sandbox`, with the compile
+    /// timeout, reusing an earlier build with the same toolchain. Returns the header to
+    /// force-include.
+    fn build_pch(
+        &self,
+        sandbox: &SandboxHandle,
+        dir: &Path,
+        headers: &[String],
+    ) -> Result<PathBuf> {
+        let content: String = headers
+            .iter()
+            .map(|header| format!("#include <{}>\n", header))
+            .collect();
+
+        let mut hasher = Sha256::new();
+        self.toolchain_key(&mut hasher, &[]);
+        hasher.update(content.as_bytes());
+        let dir = dir.join(hex::encode(hasher.finalize()));
+
+        let header = dir.join(PCH_HEADER);
+        let gch = dir.join(format!("{}.gch", PCH_HEADER));
+        if gch.is_file() {
+            return Ok(header);
+        }
+
+        fs::create_dir_all(&dir)
+            .map_err(|e| anyhow!("Failed to create {}: {}", dir.display(), e))?;
+        fs::write(&header, content)?;
+
+        // Build under a unique name and rename, so a killed build never leaves a `.gch`
+        // that later runs would trust
+        let temp = tempfile::NamedTempFile::new_in(&dir)?;
+        let mut args = self.flags.clone();
+        args.extend(["-x".to_string(), "c++-header".to_string()]);
+        args.push(header.to_string_lossy().to_string());
+        args.extend(["-o".to_string(), temp.path().to_string_lossy().to_string()]);
+        let output = sandbox::run_process(sandbox, &self.program, &args, self.timeout)
+            .map_err(|e| anyhow!("Failed to run {}: {}", self.program, e))?;
+        if output.timed_out {
+            return Err(anyhow!(
+                "Precompiling headers {} timed out",
+                headers.join(", ")
+            ));
+        }
+        if output.status != Some(0) {
+            return Err(anyhow!(
+                "Failed to precompile headers {}:\n{}",
+                headers.join(", "),
+                String::from_utf8_lossy(&output.stderr)
+            ));
+        }
+        temp.persist(&gch).map_err(|e| e.error)?;
+
+        Ok(header)
+    }
+
+    fn compile_args(&self, extra_flags: &[String]) -> Vec<String> {
+        let mut args = self.flags.clone();
+        args.extend(extra_flags.iter().cloned());
+        if let Some(pch) = &self.pch {
+            args.extend(["-include".to_string(), pch.to_string_lossy().to_string()]);
+        }
+        args.extend(
+            ["-c", SOURCE_FILE, "-o", OBJECT_FILE]
+                .into_iter()
+                .map(str::to_string),
+        );
+        args
+    }
+
+    fn link_args(&self, extra_flags: &[String]) -> Vec<String> {
+        // The driver needs options like `-fsanitize`, `-pthread` and `-static` when
+        // linking too, and ignores the ones that only matter for compiling
+        let mut args = self.profile.flags.clone();
+        args.extend(extra_flags.iter().cloned());
+        args.extend(
+            [OBJECT_FILE, "-o", BINARY_FILE]
+                .into_iter()
+                .map(str::to_string),
+        );
+        // Libraries go after the objects that need them
+        args.extend(self.link_flags.iter().cloned());
+        args
+    }
+
+    /// Compile `source` to an executable in the sandbox, taking the object file from the
+    /// cache when the same source was compiled before with the same toolchain and flags.
+    /// `extra_flags` come from the script and must be allowed by the profile.
+    pub fn compile(
+        &self,
+        sandbox: &SandboxHandle,
+        source: &str,
+        extra_flags: &[String],
+    ) -> io::Result<CompileOutput> {
+        self.check_flags(extra_flags)?;
+        let started = Instant::now();
+        sandbox.write(SOURCE_FILE, source)?;
+
+        let key = {
+            let mut hasher = Sha256::new();
+            self.toolchain_key(&mut hasher, extra_flags);
+            hasher.update(source.as_bytes());
+            hex::encode(hasher.finalize())
+        };
+        let object = sandbox.path.join(OBJECT_FILE);
+
+        let mut stderr = Vec::new();
+        let cached = match &self.cache {
+            Some(cache) => cache.fetch(&key, sandbox, &object, &mut stderr),
+            None => false,
+        };
+        metrics::COMPILE_CACHE
+            .with_label_values(&[if cached { "hit" } else { "miss" }])
+            .inc();
+
+        if !cached {
+            let output = sandbox::run_process(
+                sandbox,
+                &self.program,
+                &self.compile_args(extra_flags),
+                self.timeout,
+            )?;
+            stderr = output.stderr;
+            if output.status != Some(0) || !object.is_file() {
+                metrics::CPP_COMPILE_DURATION
+                    .with_label_values(&["error"])
+                    .observe(started.elapsed().as_secs_f64());
+                return Ok(CompileOutput {
+                    success: false,
+                    stderr,
+                    binary: None,
+                    cached,
+                    timed_out: output.timed_out,
+                });
+            }
+            if let Some(cache) = &self.cache {
+                if let Err(err) = cache.store(&key, &object, &stderr) {
+                    eprintln!("Failed to cache object {}: {}", key, err);
+                }
+            }
+        }
+
+        let output = sandbox::run_process(
+            sandbox,
+            &self.program,
+            &self.link_args(extra_flags),
+            self.timeout,
+        )?;
+        stderr.extend(output.stderr);
+        let success = output.status == Some(0);
+        metrics::CPP_COMPILE_DURATION
+            .with_label_values(&[match (success, cached) {
+                (false, _) => "error",
+                (true, true) => "cached",
+                (true, false) => "ok",
+            }])
+            .observe(started.elapsed().as_secs_f64());
+
+        Ok(CompileOutput {
+            success,
+            stderr,
+            binary: success.then(|| PathBuf::from(BINARY_FILE)),
+            cached,
+            timed_out: output.timed_out,
+        })
+    }
+
+    /// Expand `source` with the profile's flags. The output is written to a file in the
+    /// sandbox, so the disk quota bounds it along with the timeout.
+    pub fn preprocess(
+        &self,
+        sandbox: &SandboxHandle,
+        source: &str,
+    ) -> io::Result<PreprocessOutput> {
+        sandbox.write(SOURCE_FILE, source)?;
+        let expanded = sandbox.path.join(PREPROCESSED_FILE);
+        if expanded.exists() {
+            fs::remove_file(&expanded)?;
+        }
+
+        let mut args = self.flags.clone();
+        args.extend(
+            ["-E", SOURCE_FILE, "-o", PREPROCESSED_FILE]
+                .into_iter()
+                .map(str::to_string),
+        );
+        let output = sandbox::run_process(sandbox, &self.program, &args, self.timeout)?;
+
+        Ok(PreprocessOutput {
+            success: output.status == Some(0),
+            expanded: fs::read(&expanded)
+                .map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+                .unwrap_or_default(),
+            stderr: output.stderr,
+            timed_out: output.timed_out,
+        })
+    }
+
+    /// Dump the Clang AST of `source` with the profile's standard, defines and include
+    /// dirs. The dump goes to a file in the sandbox so the disk quota bounds it.
+    pub fn dump_ast(
+        &self,
+        sandbox: &SandboxHandle,
+        clang: &Clang,
+        source: &str,
+    ) -> io::Result<AstOutput> {
+        sandbox.write(SOURCE_FILE, source)?;
+
+        let mut args = vec!["-x".to_string(), "c++".to_string()];
+        args.extend(self.language_flags.iter().cloned());
+        args.extend(
+            [
+                "-fsyntax-only",
+                "-Xclang",
+                "-ast-dump=json",
+                "-fno-color-diagnostics",
+                SOURCE_FILE,
+            ]
+            .into_iter()
+            .map(str::to_string),
+        );
+        let output =
+            sandbox::run_process_to(sandbox, &clang.program, &args, AST_FILE, self.timeout)?;
+
+        let path = sandbox.path.join(AST_FILE);
+        let size = fs::metadata(&path)?.len();
+        if size > MAX_AST_BYTES {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "AST dump is {} bytes, over the {} byte limit",
+                    size, MAX_AST_BYTES
+                ),
+            ));
+        }
+
+        Ok(AstOutput {
+            success: output.status == Some(0),
+            json: fs::read(&path)?,
+            stderr: output.stderr,
+            timed_out: output.timed_out,
+        })
+    }
+}
+
+/// GCC 11 and later count diagnostic columns in display width, expanding tabs and wide
+/// characters, unless told otherwise. Clang and older GCC always count bytes.
+fn reports_display_columns(version: &str) -> bool {
+    if version.contains("clang") {
+        return false;
+    }
+    version
+        .split_whitespace()
+        .last()
+        .and_then(|release| release.split('.').next())
+        .and_then(|major| major.parse::<u32>().ok())
+        .is_some_and(|major| major >= 11)
+}
+
+/// Before Clang 11, AST dumps name the file a `#line` directive claims rather than the
+/// file a node is really in, so submissions could pass their code off as a header's
+fn check_clang_version(version: &str) -> Result<()> {
+    let major = version
+        .split("clang version ")
+        .nth(1)
+        .and_then(|rest| rest.split('.').next())
+        .and_then(|major| major.parse::<u32>().ok())
+        .ok_or_else(|| anyhow!("Unrecognised Clang version: {}", version))?;
+    if major < 11 {
+        return Err(anyhow!(
+            "Clang {} is too old for AST dumps, 11 or later is needed",
+            major
+        ));
+    }
+    Ok(())
+}
+
+/// First line of `--version`, which also checks the compiler can run at all
+fn compiler_version(program: &str) -> Result<String> {
+    let output = Command::new(program)
+        .arg("--version")
+        .output()
+        .map_err(|e| anyhow!("Failed to run compiler {}: {}", program, e))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "Compiler {} --version exited with {}",
+            program,
+            output.status
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .to_string())
+}
+
+/// Object files keyed by the hash of their source and toolchain, evicting the least
+/// recently used once over its size limit
+#[derive(Debug)]
+struct ObjectCache {
+    dir: PathBuf,
+    max_bytes: u64,
+    /// Serialises eviction so concurrent stores don't both scan and delete
+    evicting: Mutex<()>,
+}
+
+impl ObjectCache {
+    fn new(dir: PathBuf, max_bytes: u64) -> Result<Self> {
+        fs::create_dir_all(&dir)
+            .map_err(|e| anyhow!("Failed to create compile cache {}: {}", dir.display(), e))?;
+        let cache = Self {
+            dir,
+            max_bytes,
+            evicting: Mutex::new(()),
+        };
+        cache.evict();
+        Ok(cache)
+    }
+
+    fn object_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.o", key))
+    }
+
+    fn stderr_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.stderr", key))
+    }
+
+    /// Copy a cached object to `dest` in the sandbox, within its quota, along with the
+    /// diagnostics it was compiled with
+    fn fetch(&self, key: &str, sandbox: &SandboxHandle, dest: &Path, stderr: &mut Vec<u8>) -> bool {
+        let object = self.object_path(key);
+        let Ok(size) = fs::metadata(&object).map(|m| m.len()) else {
+            return false;
+        };
+        if sandbox.reserve(size).is_err() || fs::copy(&object, dest).is_err() {
+            return false;
+        }
+        *stderr = fs::read(self.stderr_path(key)).unwrap_or_default();
+
+        // Recency for eviction is tracked through the modification time
+        if let Ok(file) = fs::File::options().write(true).open(&object) {
+            let _ = file.set_modified(SystemTime::now());
+        }
+        true
+    }
+
+    fn store(&self, key: &str, object: &Path, stderr: &[u8]) -> io::Result<()> {
+        // Write both under unique names and rename, so readers never see a partial file.
+        // The object goes last: its presence is what makes the entry a hit.
+        let temp_stderr = tempfile::NamedTempFile::new_in(&self.dir)?;
+        fs::write(temp_stderr.path(), stderr)?;
+        let temp_object = tempfile::NamedTempFile::new_in(&self.dir)?;
+        fs::copy(object, temp_object.path())?;
+        temp_stderr
+            .persist(self.stderr_path(key))
+            .map_err(|e| e.error)?;
+        temp_object
+            .persist(self.object_path(key))
+            .map_err(|e| e.error)?;
+        self.evict();
+        Ok(())
+    }
+
+    fn evict(&self) {
+        let _guard = self.evicting.lock().unwrap();
+
+        let mut objects: Vec<(SystemTime, u64, PathBuf)> = fs::read_dir(&self.dir)
+            .into_iter()
+            .flatten()
+            .filter_map(Result::ok)
+            .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "o"))
+            .filter_map(|entry| {
+                let metadata = entry.metadata().ok()?;
+                Some((metadata.modified().ok()?, metadata.len(), entry.path()))
+            })
+            .collect();
+        let mut total: u64 = objects.iter().map(|(_, size, _)| size).sum();
+
+        objects.sort();
+        for (_, size, path) in objects {
+            if total <= self.max_bytes {
+                break;
+            }
+            if fs::remove_file(&path).is_ok() {
+                let _ = fs::remove_file(path.with_extension("stderr"));
+                total -= size;
+                metrics::COMPILE_CACHE_EVICTIONS.inc();
+            }
+        }
+        metrics::COMPILE_CACHE_BYTES.set(total as i64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::CompileConfig;
+    use crate::sandbox::{Sandbox, SandboxLimits};
+
+    #[test]
+    fn profile_flags_reach_the_linker() {
+        let profile = ToolchainProfile {
+            flags: vec!["-fsanitize=address".to_string()],
+            ..ToolchainProfile::default()
+        };
+        let config = ChallengeConfig {
+            compile: CompileConfig {
+                profiles: BTreeMap::from([("asan".to_string(), profile)]),
+                default_profile: "asan".to_string(),
+                ..CompileConfig::default()
+            },
+            ..ChallengeConfig::default()
+        };
+        let bucket = tempfile::tempdir().unwrap();
+        let cache = tempfile::tempdir().unwrap();
+        let toolchains = Toolchains::new(&config, bucket.path(), cache.path(), 0).unwrap();
+
+        let root = tempfile::tempdir().unwrap();
+        let sandbox = Sandbox::new(root.path()).unwrap();
+        let handle = sandbox.handle(SandboxLimits::default());
+        let compiler = toolchains.get(None, &handle).unwrap();
+        let source = "int main() { int a[2] = {0}; return a[0]; }\n";
+        let output = compiler.compile(&handle, source, &[]).unwrap();
+        assert!(
+            output.success,
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+}
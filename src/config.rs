@@ -0,0 +1,232 @@
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use std::{collections::BTreeMap, path::Path};
+
+/// Optional per-challenge settings, read from the data bucket
+pub const CHALLENGE_CONFIG_FILE: &str = "challenge.json";
+
+/// Compiler used when the challenge config doesn't name one
+pub const DEFAULT_COMPILER: &str = "g++";
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChallengeConfig {
+    /// Name used in logs, defaults to the bucket directory name
+    pub name: Option<String>,
+    /// C++ compiler executable, name or path
+    pub compiler: Option<String>,
+    pub entry_points: EntryPoints,
+    /// Extra script functions callable by name; anything not listed here is not callable
+    pub actions: BTreeMap<String, ActionConfig>,
+    pub submission: SubmissionConfig,
+    pub solve: SolveConfig,
+    pub sandbox: SandboxConfig,
+    pub compile: CompileConfig,
+    pub redact: RedactConfig,
+}
+
+/// Secrets kept out of compiler output before the script sees it
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RedactConfig {
+    /// Literal secrets, masked along with their hex, base64 and reversed spellings
+    pub secrets: Vec<String>,
+    /// Bucket files whose trimmed contents are secrets, such as `flag.txt`
+    pub secret_files: Vec<String>,
+    /// Replacement for masked secrets and hidden template lines
+    pub mask: String,
+}
+
+impl Default for RedactConfig {
+    fn default() -> Self {
+        Self {
+            secrets: Vec::new(),
+            secret_files: Vec::new(),
+            mask: "[redacted]".to_string(),
+        }
+    }
+}
+
+/// How `ctx.sandbox().compile(..)` builds submissions
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CompileConfig {
+    /// Named toolchains scripts can pick from; a lone `default` profile when empty
+    pub profiles: BTreeMap<String, ToolchainProfile>,
+    /// Profile used when the script doesn't name one
+    pub default_profile: String,
+    /// Wall clock limit for each compiler run
+    pub timeout_ms: u64,
+    /// Clang executable used by `jailapi::cpp::ast`, which is unavailable when it can't run
+    pub clang: String,
+}
+
+impl Default for CompileConfig {
+    fn default() -> Self {
+        Self {
+            profiles: BTreeMap::new(),
+            default_profile: DEFAULT_PROFILE.to_string(),
+            timeout_ms: 30_000,
+            clang: "clang".to_string(),
+        }
+    }
+}
+
+/// Name of the profile used when a challenge declares none
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// A compiler together with everything passed to it
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ToolchainProfile {
+    /// Compiler executable, defaults to the challenge's `compiler`
+    pub compiler: Option<String>,
+    /// Language standard, passed as `-std=<std>`
+    pub std: Option<String>,
+    /// Flags passed when compiling
+    pub flags: Vec<String>,
+    /// Macros as `NAME` or `NAME=VALUE`, passed as `-D`
+    pub defines: Vec<String>,
+    /// Bucket directories passed as `-I`
+    pub include_dirs: Vec<String>,
+    /// Flags passed when linking
+    pub link_flags: Vec<String>,
+    /// Headers precompiled at startup and force-included into every compile
+    pub precompiled_headers: Vec<String>,
+    /// Extra flags a script may pass along, exactly or as a `prefix*` pattern
+    pub allowed_flags: Vec<String>,
+}
+
+impl ToolchainProfile {
+    /// Whether a script-supplied flag is allowed by this profile
+    pub fn allows_flag(&self, flag: &str) -> bool {
+        self.allowed_flags
+            .iter()
+            .any(|allowed| match allowed.strip_suffix('*') {
+                Some(prefix) => flag.starts_with(prefix),
+                None => flag == allowed,
+            })
+    }
+}
+
+impl CompileConfig {
+    /// Declared profiles, or the implicit default one
+    pub fn profiles(&self) -> BTreeMap<String, ToolchainProfile> {
+        if self.profiles.is_empty() {
+            BTreeMap::from([(DEFAULT_PROFILE.to_string(), ToolchainProfile::default())])
+        } else {
+            self.profiles.clone()
+        }
+    }
+}
+
+/// Limits for work done inside a submission's sandbox
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SandboxConfig {
+    /// Bucket subdirectory copied, read-only, into every new sandbox
+    pub template: Option<String>,
+}
+
+/// How to recognise a solve in `check` output and where to report it
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SolveConfig {
+    /// CTFd challenge id that solves are recorded against
+    pub challenge_id: Option<u64>,
+    /// Substrings of a successful `check` output that mark a solve
+    pub markers: Vec<String>,
+    /// Regex matching the flag in a successful `check` output
+    pub flag_pattern: Option<String>,
+}
+
+/// What to do with a text submission that is not valid UTF-8
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InvalidUtf8 {
+    /// Reject the request with a 400 error
+    #[default]
+    Reject,
+    /// Hand the raw body to `check` as Rune `Bytes`
+    Bytes,
+}
+
+/// HTTP-level validation of submissions and action calls
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SubmissionConfig {
+    /// Maximum request body size in bytes
+    pub max_body_bytes: usize,
+    pub invalid_utf8: InvalidUtf8,
+    /// Accepted media types for `/api/submit`, any when unset
+    pub content_types: Option<Vec<String>>,
+}
+
+impl Default for SubmissionConfig {
+    fn default() -> Self {
+        Self {
+            // Same as axum's default body limit
+            max_body_bytes: 2 * 1024 * 1024,
+            invalid_utf8: InvalidUtf8::default(),
+            content_types: None,
+        }
+    }
+}
+
+/// Script function names backing the built-in routes
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EntryPoints {
+    /// Called as `collect(ctx)`
+    pub collect: String,
+    /// Called as `check(ctx, user_input)`
+    pub check: String,
+}
+
+impl Default for EntryPoints {
+    fn default() -> Self {
+        Self {
+            collect: "collect".to_string(),
+            check: "check".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ActionConfig {
+    /// Script function to call, defaults to the action name
+    pub function: Option<String>,
+    /// Number of arguments after `ctx`
+    pub args: usize,
+}
+
+impl ChallengeConfig {
+    /// Load the bucket's challenge config, falling back to defaults when there is none
+    pub fn load(bucket_path: &Path) -> Result<Self> {
+        let path = bucket_path.join(CHALLENGE_CONFIG_FILE);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let content = std::fs::read_to_string(&path)
+            .map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+        serde_json::from_str(&content).map_err(|e| anyhow!("Invalid {}: {}", path.display(), e))
+    }
+
+    pub fn compiler(&self) -> &str {
+        self.compiler.as_deref().unwrap_or(DEFAULT_COMPILER)
+    }
+
+    /// Compiler executable of a toolchain profile
+    pub fn profile_compiler<'a>(&'a self, profile: &'a ToolchainProfile) -> &'a str {
+        profile.compiler.as_deref().unwrap_or(self.compiler())
+    }
+
+    /// Resolve a whitelisted action to its script function
+    pub fn action<'a>(&'a self, name: &'a str) -> Option<(&'a str, &'a ActionConfig)> {
+        self.actions
+            .get(name)
+            .map(|action| (action.function.as_deref().unwrap_or(name), action))
+    }
+}
@@ -0,0 +1,244 @@
+use serde::Deserialize;
+
+/// Nodes kept from one dump; anything past this is dropped and the AST marked truncated
+pub const MAX_NODES: usize = 100_000;
+
+/// A node of the submission's AST, flattened in pre-order
+#[derive(Clone, Debug)]
+pub struct AstNode {
+    /// Clang's node kind, e.g. `FunctionDecl`, `CallExpr` or `LambdaExpr`
+    pub kind: String,
+    /// Declared or referenced name; the callee's name for calls
+    pub name: Option<String>,
+    /// Nesting below the top-level declaration the node belongs to
+    pub depth: usize,
+    /// 1-based line and column in the compiled file, where the code was written or
+    /// macro-expanded
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+}
+
+/// Function, member function, operator and user-defined literal calls
+pub fn is_call(kind: &str) -> bool {
+    kind.ends_with("CallExpr") || kind == "UserDefinedLiteral"
+}
+
+/// Declarations written in one file, leaving out headers it included
+#[derive(Debug, Default)]
+pub struct Ast {
+    pub nodes: Vec<AstNode>,
+    pub truncated: bool,
+}
+
+#[derive(Deserialize)]
+struct RawNode {
+    kind: String,
+    name: Option<String>,
+    #[serde(default, rename = "isImplicit")]
+    is_implicit: bool,
+    #[serde(default)]
+    loc: RawLoc,
+    #[serde(default)]
+    range: RawRange,
+    #[serde(rename = "referencedDecl")]
+    referenced_decl: Option<RawDeclRef>,
+    #[serde(default)]
+    inner: Vec<RawNode>,
+}
+
+#[derive(Default, Deserialize)]
+struct RawRange {
+    #[serde(default)]
+    begin: RawLoc,
+    #[serde(default)]
+    end: RawLoc,
+}
+
+#[derive(Default, Deserialize)]
+struct RawLoc {
+    file: Option<String>,
+    line: Option<usize>,
+    col: Option<usize>,
+    #[serde(rename = "spellingLoc")]
+    spelling: Option<Box<RawLoc>>,
+    #[serde(rename = "expansionLoc")]
+    expansion: Option<Box<RawLoc>>,
+}
+
+#[derive(Deserialize)]
+struct RawDeclRef {
+    name: Option<String>,
+}
+
+/// Clang only writes a location's file and line when they differ from the previous
+/// location it wrote, so they have to be tracked through the dump in output order.
+/// Both are where the code really is: names and lines set by `#line` are written
+/// separately, as `presumedFile` and `presumedLine`, and ignored here.
+#[derive(Default)]
+struct LocationTracker {
+    file: String,
+    line: usize,
+}
+
+/// Where a node starts: whether that's in the main file, then its line and column
+type Position = (bool, usize, usize);
+
+impl LocationTracker {
+    /// Update from `loc`, returning its line and column after macro expansion
+    fn visit(&mut self, loc: &RawLoc) -> Option<(usize, usize)> {
+        if let Some(spelling) = &loc.spelling {
+            self.visit(spelling);
+        }
+        if let Some(expansion) = &loc.expansion {
+            return self.visit(expansion);
+        }
+        if let Some(file) = &loc.file {
+            self.file = file.clone();
+        }
+        if let Some(line) = loc.line {
+            self.line = line;
+        }
+        loc.col.map(|column| (self.line, column))
+    }
+
+    fn position(&mut self, loc: &RawLoc, main_file: &str) -> Option<Position> {
+        self.visit(loc)
+            .map(|(line, column)| (self.file == main_file, line, column))
+    }
+
+    /// Update from a node's location and range, in the order Clang writes them
+    fn node(&mut self, node: &RawNode, main_file: &str) -> Option<Position> {
+        let loc = self.position(&node.loc, main_file);
+        let begin = self.position(&node.range.begin, main_file);
+        self.position(&node.range.end, main_file);
+        loc.or(begin)
+    }
+}
+
+struct Builder<'a> {
+    main_file: &'a str,
+    tracker: LocationTracker,
+    ast: Ast,
+}
+
+impl Builder<'_> {
+    /// Walk `node` and its children, keeping them when `keep` holds, or for a top-level
+    /// declaration when it's in the main file. Every node is walked either way, so later
+    /// locations resolve.
+    fn walk(&mut self, node: &RawNode, depth: usize, keep: Option<bool>) {
+        let position = self.tracker.node(node, self.main_file);
+        let in_main = position.is_some_and(|(in_main, _, _)| in_main);
+        // Implicit declarations repeat code, like the closure class of a lambda
+        let keep = keep.unwrap_or(in_main) && !node.is_implicit;
+
+        let index = self.ast.nodes.len();
+        if keep {
+            if index < MAX_NODES {
+                let (line, column) = match position {
+                    Some((true, line, column)) => (Some(line), Some(column)),
+                    _ => (None, None),
+                };
+                self.ast.nodes.push(AstNode {
+                    kind: node.kind.clone(),
+                    name: node
+                        .name
+                        .clone()
+                        .or_else(|| node.referenced_decl.as_ref()?.name.clone()),
+                    depth,
+                    line,
+                    column,
+                });
+            } else {
+                self.ast.truncated = true;
+            }
+        }
+
+        for child in &node.inner {
+            self.walk(child, depth + 1, Some(keep));
+        }
+
+        if keep
+            && self
+                .ast
+                .nodes
+                .get(index)
+                .is_some_and(|node| is_call(&node.kind))
+        {
+            self.ast.nodes[index].name = self.callee(index);
+        }
+    }
+
+    /// Name of what a call refers to, from the first reference in its first child
+    fn callee(&self, index: usize) -> Option<String> {
+        let child_depth = self.ast.nodes[index].depth + 1;
+        let first_child = self.ast.nodes.get(index + 1)?;
+        std::iter::once(first_child)
+            .chain(
+                self.ast.nodes[index + 2..]
+                    .iter()
+                    .take_while(|n| n.depth > child_depth),
+            )
+            .find(|n| n.kind == "DeclRefExpr" || n.kind == "MemberExpr")
+            .and_then(|n| n.name.clone())
+    }
+}
+
+/// Simplify Clang's `-ast-dump=json` output to the top-level declarations written in
+/// `main_file`
+pub fn parse(json: &[u8], main_file: &str) -> Result<Ast, serde_json::Error> {
+    let root: RawNode = serde_json::from_slice(json)?;
+    let mut builder = Builder {
+        main_file,
+        tracker: LocationTracker::default(),
+        ast: Ast::default(),
+    };
+    builder.tracker.node(&root, main_file);
+    for decl in &root.inner {
+        builder.walk(decl, 0, None);
+    }
+    Ok(builder.ast)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A header declaration, then `main` calling `system` after `#line 1 "x.h"`
+    const DUMP: &str = r#"{"kind":"TranslationUnitDecl","loc":{},"range":{"begin":{},"end":{}},"inner":[
+        {"kind":"TypedefDecl","loc":{},"range":{"begin":{},"end":{}},"isImplicit":true,"name":"__int128_t"},
+        {"kind":"FunctionDecl","loc":{"offset":10,"file":"/usr/include/stdlib.h","line":90,"col":12,"includedFrom":{"file":"main.cpp"}},
+         "range":{"begin":{"offset":0,"col":1},"end":{"offset":40,"line":91,"col":3}},"name":"system"},
+        {"kind":"FunctionDecl","loc":{"offset":20,"file":"main.cpp","line":2,"col":5},
+         "range":{"begin":{"offset":16,"col":1},"end":{"offset":60,"line":5,"col":1}},"name":"main","inner":[
+          {"kind":"CompoundStmt","range":{"begin":{"offset":27,"line":2,"col":12},"end":{"offset":60,"line":5,"col":1}},"inner":[
+            {"kind":"CallExpr","range":{"begin":{"offset":45,"line":4,"col":3,"presumedFile":"x.h","presumedLine":1},"end":{"offset":56,"col":14}},"inner":[
+              {"kind":"ImplicitCastExpr","range":{"begin":{"offset":45,"col":3},"end":{"offset":45,"col":3}},"inner":[
+                {"kind":"DeclRefExpr","range":{"begin":{"offset":45,"col":3},"end":{"offset":45,"col":3}},
+                 "referencedDecl":{"kind":"FunctionDecl","name":"system"}}]}]}]}]}]}"#;
+
+    #[test]
+    fn keeps_main_file_declarations() {
+        let ast = parse(DUMP.as_bytes(), "main.cpp").unwrap();
+        let kinds: Vec<&str> = ast.nodes.iter().map(|node| node.kind.as_str()).collect();
+        assert_eq!(
+            kinds,
+            [
+                "FunctionDecl",
+                "CompoundStmt",
+                "CallExpr",
+                "ImplicitCastExpr",
+                "DeclRefExpr"
+            ]
+        );
+        assert_eq!(ast.nodes[0].name.as_deref(), Some("main"));
+        assert!(!ast.truncated);
+    }
+
+    #[test]
+    fn line_directives_do_not_move_code() {
+        let ast = parse(DUMP.as_bytes(), "main.cpp").unwrap();
+        let call = &ast.nodes[2];
+        assert_eq!(call.name.as_deref(), Some("system"));
+        assert_eq!((call.line, call.column), (Some(4), Some(3)));
+    }
+}
@@ -0,0 +1,820 @@
+use std::fmt;
+
+const KEYWORDS: &[&str] = &[
+    "alignas",
+    "alignof",
+    "asm",
+    "auto",
+    "bool",
+    "break",
+    "case",
+    "catch",
+    "char",
+    "char8_t",
+    "char16_t",
+    "char32_t",
+    "class",
+    "concept",
+    "const",
+    "consteval",
+    "constexpr",
+    "constinit",
+    "const_cast",
+    "continue",
+    "co_await",
+    "co_return",
+    "co_yield",
+    "decltype",
+    "default",
+    "delete",
+    "do",
+    "double",
+    "dynamic_cast",
+    "else",
+    "enum",
+    "explicit",
+    "export",
+    "extern",
+    "false",
+    "float",
+    "for",
+    "friend",
+    "goto",
+    "if",
+    "inline",
+    "int",
+    "long",
+    "mutable",
+    "namespace",
+    "new",
+    "noexcept",
+    "nullptr",
+    "operator",
+    "private",
+    "protected",
+    "public",
+    "register",
+    "reinterpret_cast",
+    "requires",
+    "return",
+    "short",
+    "signed",
+    "sizeof",
+    "static",
+    "static_assert",
+    "static_cast",
+    "struct",
+    "switch",
+    "template",
+    "this",
+    "thread_local",
+    "throw",
+    "true",
+    "try",
+    "typedef",
+    "typeid",
+    "typename",
+    "union",
+    "unsigned",
+    "using",
+    "virtual",
+    "void",
+    "volatile",
+    "wchar_t",
+    "while",
+];
+
+/// Identifiers that spell operators
+const ALTERNATIVE_TOKENS: &[(&str, &str)] = &[
+    ("and", "&&"),
+    ("and_eq", "&="),
+    ("bitand", "&"),
+    ("bitor", "|"),
+    ("compl", "~"),
+    ("not", "!"),
+    ("not_eq", "!="),
+    ("or", "||"),
+    ("or_eq", "|="),
+    ("xor", "^"),
+    ("xor_eq", "^="),
+];
+
+/// Punctuators, longest first, with the canonical spelling of digraphs
+const PUNCTUATORS: &[(&str, &str)] = &[
+    ("%:%:", "##"),
+    ("...", "..."),
+    ("<=>", "<=>"),
+    ("<<=", "<<="),
+    (">>=", ">>="),
+    ("->*", "->*"),
+    ("<%", "{"),
+    ("%>", "}"),
+    ("<:", "["),
+    (":>", "]"),
+    ("%:", "#"),
+    ("##", "##"),
+    ("::", "::"),
+    (".*", ".*"),
+    ("->", "->"),
+    ("++", "++"),
+    ("--", "--"),
+    ("<<", "<<"),
+    (">>", ">>"),
+    ("<=", "<="),
+    (">=", ">="),
+    ("==", "=="),
+    ("!=", "!="),
+    ("&&", "&&"),
+    ("||", "||"),
+    ("+=", "+="),
+    ("-=", "-="),
+    ("*=", "*="),
+    ("/=", "/="),
+    ("%=", "%="),
+    ("&=", "&="),
+    ("|=", "|="),
+    ("^=", "^="),
+    ("{", "{"),
+    ("}", "}"),
+    ("[", "["),
+    ("]", "]"),
+    ("#", "#"),
+    ("(", "("),
+    (")", ")"),
+    (";", ";"),
+    (":", ":"),
+    ("?", "?"),
+    (".", "."),
+    ("+", "+"),
+    ("-", "-"),
+    ("*", "*"),
+    ("/", "/"),
+    ("%", "%"),
+    ("^", "^"),
+    ("&", "&"),
+    ("|", "|"),
+    ("~", "~"),
+    ("!", "!"),
+    ("=", "="),
+    ("<", "<"),
+    (">", ">"),
+    (",", ","),
+];
+
+const TRIGRAPHS: &[(char, char)] = &[
+    ('=', '#'),
+    ('/', '\\'),
+    ('\'', '^'),
+    ('(', '['),
+    (')', ']'),
+    ('!', '|'),
+    ('<', '{'),
+    ('>', '}'),
+    ('-', '~'),
+];
+
+const STRING_PREFIXES: &[&str] = &["u8", "u", "U", "L"];
+const RAW_STRING_PREFIXES: &[&str] = &["R", "u8R", "uR", "UR", "LR"];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TokenKind {
+    Identifier,
+    Keyword,
+    Number,
+    String,
+    Char,
+    Punctuator,
+    /// `#name` starting a preprocessor line, with the directive name as its text; GNU
+    /// linemarkers like `# 33 "file"` are `line`
+    Directive,
+    /// `<file>` or `"file"` after `#include`
+    HeaderName,
+    Comment,
+    /// A character that starts no token, such as `@` or a stray `\`
+    Unknown,
+}
+
+impl TokenKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TokenKind::Identifier => "identifier",
+            TokenKind::Keyword => "keyword",
+            TokenKind::Number => "number",
+            TokenKind::String => "string",
+            TokenKind::Char => "char",
+            TokenKind::Punctuator => "punctuator",
+            TokenKind::Directive => "directive",
+            TokenKind::HeaderName => "header_name",
+            TokenKind::Comment => "comment",
+            TokenKind::Unknown => "unknown",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Token {
+    pub kind: TokenKind,
+    /// Canonical form: digraphs, trigraphs, alternative tokens, line splices and `\u`
+    /// escapes in identifiers resolved
+    pub text: String,
+    /// Exactly as written in the source
+    pub spelling: String,
+    /// Byte offset of the spelling in the source
+    pub offset: usize,
+    /// 1-based, columns in bytes
+    pub line: usize,
+    pub column: usize,
+    /// Written through a digraph, trigraph, alternative token, line splice or universal
+    /// character name rather than plainly
+    pub alternative: bool,
+}
+
+#[derive(Debug)]
+pub struct LexError {
+    pub message: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for LexError {}
+
+/// How the compiler reads source before tokenizing it
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LexOptions {
+    /// Replace `??x` trigraphs. GCC only does with `-trigraphs` or an ISO standard before
+    /// C++17; otherwise they are plain characters, though still marked `alternative`.
+    pub trigraphs: bool,
+}
+
+/// Split C++ source into preprocessing tokens, dropping whitespace
+pub fn tokenize(source: &str, options: LexOptions) -> Result<Vec<Token>, LexError> {
+    Lexer::new(source, options).run()
+}
+
+/// Source after translation phases 1 and 2, each character remembering where it was
+/// written, so tokens can be matched on meaning and still report their spelling
+struct Lexer<'a> {
+    source: &'a str,
+    chars: Vec<char>,
+    /// Byte range in `source` of each character of `chars`
+    spans: Vec<(usize, usize)>,
+    line_starts: Vec<usize>,
+    /// Byte ranges of trigraphs left undecoded, which still mark tokens as alternative
+    trigraphs: Vec<(usize, usize)>,
+    pos: usize,
+    tokens: Vec<Token>,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(source: &'a str, options: LexOptions) -> Self {
+        let raw: Vec<(usize, char)> = source.char_indices().collect();
+        let mut chars = Vec::new();
+        let mut spans = Vec::new();
+        let mut trigraphs = Vec::new();
+
+        let mut i = 0;
+        let mut pending_start: Option<usize> = None;
+        while i < raw.len() {
+            let (start, c) = raw[i];
+            let (c, len) = match (c, raw.get(i + 1), raw.get(i + 2)) {
+                ('?', Some((_, '?')), Some((_, third))) => {
+                    match TRIGRAPHS.iter().find(|(from, _)| from == third) {
+                        Some((_, to)) if options.trigraphs => (*to, 3),
+                        Some(_) => {
+                            trigraphs.push((start, start + 3));
+                            (c, 1)
+                        }
+                        None => (c, 1),
+                    }
+                }
+                _ => (c, 1),
+            };
+            let end = raw.get(i + len).map_or(source.len(), |(offset, _)| *offset);
+
+            // A backslash right before a newline joins the lines; its span is folded into
+            // the next character so spellings keep it
+            if c == '\\' {
+                let newline = match (raw.get(i + len), raw.get(i + len + 1)) {
+                    (Some((_, '\n')), _) => Some(1),
+                    (Some((_, '\r')), Some((_, '\n'))) => Some(2),
+                    _ => None,
+                };
+                if let Some(newline_len) = newline {
+                    pending_start.get_or_insert(start);
+                    i += len + newline_len;
+                    continue;
+                }
+            }
+
+            chars.push(c);
+            spans.push((pending_start.take().unwrap_or(start), end));
+            i += len;
+        }
+
+        let line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+
+        Self {
+            source,
+            chars,
+            spans,
+            line_starts,
+            trigraphs,
+            pos: 0,
+            tokens: Vec::new(),
+        }
+    }
+
+    fn peek(&self, ahead: usize) -> Option<char> {
+        self.chars.get(self.pos + ahead).copied()
+    }
+
+    fn starts_with(&self, text: &str) -> bool {
+        text.chars()
+            .enumerate()
+            .all(|(i, c)| self.peek(i) == Some(c))
+    }
+
+    fn offset_of(&self, index: usize) -> usize {
+        self.spans
+            .get(index)
+            .map_or(self.source.len(), |(start, _)| *start)
+    }
+
+    fn line_column(&self, offset: usize) -> (usize, usize) {
+        let line = self.line_starts.partition_point(|&start| start <= offset);
+        (line, offset - self.line_starts[line - 1] + 1)
+    }
+
+    fn error(&self, index: usize, message: &str) -> LexError {
+        let (line, column) = self.line_column(self.offset_of(index));
+        LexError {
+            message: message.to_string(),
+            line,
+            column,
+        }
+    }
+
+    fn push(&mut self, kind: TokenKind, start: usize, text: String) {
+        let offset = self.offset_of(start);
+        let end = self.spans[self.pos - 1].1;
+        let spelling = &self.source[offset..end];
+
+        // Trigraphs and splices show between spelling and characters, the rest between
+        // characters and canonical text
+        let chars = self.text(start);
+        let alternative = spelling != chars
+            || self.has_trigraph(offset, end)
+            || match kind {
+                TokenKind::Directive => chars.starts_with("%:"),
+                _ => text != chars,
+            };
+        self.push_token(kind, start, text, alternative);
+    }
+
+    fn push_token(&mut self, kind: TokenKind, start: usize, text: String, alternative: bool) {
+        let offset = self.offset_of(start);
+        let end = self.spans[self.pos - 1].1;
+        let (line, column) = self.line_column(offset);
+        let spelling = self.source[offset..end].to_string();
+
+        self.tokens.push(Token {
+            kind,
+            text,
+            spelling,
+            offset,
+            line,
+            column,
+            alternative,
+        });
+    }
+
+    /// Whether an undecoded trigraph overlaps the byte range
+    fn has_trigraph(&self, start: usize, end: usize) -> bool {
+        self.trigraphs.iter().any(|&(s, e)| s < end && start < e)
+    }
+
+    fn text(&self, start: usize) -> String {
+        self.chars[start..self.pos].iter().collect()
+    }
+
+    fn run(mut self) -> Result<Vec<Token>, LexError> {
+        let mut line_start = true;
+
+        while let Some(c) = self.peek(0) {
+            let start = self.pos;
+
+            if c == '\n' {
+                line_start = true;
+                self.pos += 1;
+                continue;
+            }
+            if c.is_whitespace() {
+                self.pos += 1;
+                continue;
+            }
+
+            if self.starts_with("//") {
+                while self.peek(0).is_some_and(|c| c != '\n') {
+                    self.pos += 1;
+                }
+                let text = self.text(start);
+                self.push(TokenKind::Comment, start, text);
+                continue;
+            }
+            if self.starts_with("/*") {
+                self.pos += 2;
+                while !self.starts_with("*/") {
+                    if self.peek(0).is_none() {
+                        return Err(self.error(start, "Unterminated comment"));
+                    }
+                    self.pos += 1;
+                }
+                self.pos += 2;
+                let text = self.text(start);
+                self.push(TokenKind::Comment, start, text);
+                continue;
+            }
+
+            let at_line_start = std::mem::replace(&mut line_start, false);
+
+            if is_identifier_start(c) || self.ucn_len().is_some() {
+                let name = self.identifier();
+                if let Some(quote @ ('"' | '\'')) = self.peek(0) {
+                    if RAW_STRING_PREFIXES.contains(&name.as_str()) && quote == '"' {
+                        self.raw_string(start)?;
+                        continue;
+                    }
+                    if STRING_PREFIXES.contains(&name.as_str()) {
+                        self.quoted(start, quote)?;
+                        continue;
+                    }
+                }
+                match ALTERNATIVE_TOKENS.iter().find(|(word, _)| *word == name) {
+                    Some((_, operator)) => {
+                        self.push(TokenKind::Punctuator, start, operator.to_string())
+                    }
+                    None if KEYWORDS.contains(&name.as_str()) => {
+                        self.push(TokenKind::Keyword, start, name)
+                    }
+                    None => self.push(TokenKind::Identifier, start, name),
+                }
+                continue;
+            }
+
+            if c.is_ascii_digit() || (c == '.' && self.peek(1).is_some_and(|c| c.is_ascii_digit()))
+            {
+                self.number();
+                let text = self.text(start);
+                self.push(TokenKind::Number, start, text);
+                continue;
+            }
+
+            if c == '"' || c == '\'' {
+                self.quoted(start, c)?;
+                continue;
+            }
+
+            // `<::` is `<` followed by `::` unless the next character is `:` or `>`
+            let digraph_exception =
+                self.starts_with("<::") && !matches!(self.peek(3), Some(':' | '>'));
+            let punctuator = PUNCTUATORS
+                .iter()
+                .find(|(spelling, _)| self.starts_with(spelling))
+                .filter(|_| !digraph_exception);
+            let (spelling, text) = match punctuator {
+                Some(found) => *found,
+                None if digraph_exception => ("<", "<"),
+                None => {
+                    self.pos += 1;
+                    self.push(TokenKind::Unknown, start, c.to_string());
+                    continue;
+                }
+            };
+            self.pos += spelling.chars().count();
+
+            if text == "#" && at_line_start {
+                self.directive(start);
+            } else {
+                self.push(TokenKind::Punctuator, start, text.to_string());
+            }
+        }
+
+        Ok(self.tokens)
+    }
+
+    /// Length of a `\uXXXX` or `\UXXXXXXXX` escape at the current position
+    fn ucn_len(&self) -> Option<usize> {
+        let digits = match (self.peek(0), self.peek(1)) {
+            (Some('\\'), Some('u')) => 4,
+            (Some('\\'), Some('U')) => 8,
+            _ => return None,
+        };
+        (2..2 + digits)
+            .all(|i| self.peek(i).is_some_and(|c| c.is_ascii_hexdigit()))
+            .then_some(2 + digits)
+    }
+
+    /// Read an identifier, decoding universal character names
+    fn identifier(&mut self) -> String {
+        let mut name = String::new();
+        loop {
+            if let Some(len) = self.ucn_len() {
+                let hex: String = self.chars[self.pos + 2..self.pos + len].iter().collect();
+                name.extend(u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32));
+                self.pos += len;
+                continue;
+            }
+            match self.peek(0) {
+                Some(c) if is_identifier_continue(c) => {
+                    name.push(c);
+                    self.pos += 1;
+                }
+                _ => return name,
+            }
+        }
+    }
+
+    /// pp-number: digits, letters, `_`, `.`, digit separators and signed exponents
+    fn number(&mut self) {
+        self.pos += 1;
+        while let Some(c) = self.peek(0) {
+            match c {
+                'e' | 'E' | 'p' | 'P' if matches!(self.peek(1), Some('+' | '-')) => self.pos += 2,
+                '\'' if self.peek(1).is_some_and(is_identifier_continue) => self.pos += 2,
+                c if is_identifier_continue(c) || c == '.' => self.pos += 1,
+                _ => break,
+            }
+        }
+    }
+
+    /// String or character literal, with the prefix already consumed
+    fn quoted(&mut self, start: usize, quote: char) -> Result<(), LexError> {
+        self.pos += 1;
+        loop {
+            match self.peek(0) {
+                None | Some('\n') => {
+                    let what = if quote == '"' { "string" } else { "character" };
+                    return Err(self.error(start, &format!("Unterminated {} literal", what)));
+                }
+                Some('\\') => self.pos += 2,
+                Some(c) if c == quote => break,
+                Some(_) => self.pos += 1,
+            }
+        }
+        self.pos += 1;
+        self.suffix();
+
+        let kind = if quote == '"' {
+            TokenKind::String
+        } else {
+            TokenKind::Char
+        };
+        let text = self.text(start);
+        self.push(kind, start, text);
+        Ok(())
+    }
+
+    /// `R"delim(...)delim"`, with the prefix already consumed. Phases 1 and 2 are reverted
+    /// inside raw strings, so everything from the opening quote is read as written.
+    fn raw_string(&mut self, start: usize) -> Result<(), LexError> {
+        let quote = self.pos;
+        let body_start = self.spans[quote].1;
+        let body = &self.source[body_start..];
+
+        let mut delimiter_len = None;
+        for (i, c) in body.char_indices() {
+            if c == '(' {
+                delimiter_len = Some(i);
+                break;
+            }
+            if c.is_whitespace() || c == ')' || c == '\\' || i >= 16 {
+                break;
+            }
+        }
+        let Some(delimiter_len) = delimiter_len else {
+            return Err(self.error(start, "Invalid raw string delimiter"));
+        };
+        let terminator = format!("){}\"", &body[..delimiter_len]);
+        let Some(close) = body[delimiter_len..].find(&terminator) else {
+            return Err(self.error(start, "Unterminated raw string literal"));
+        };
+        let end = body_start + delimiter_len + close + terminator.len();
+
+        self.pos = self
+            .spans
+            .partition_point(|&(span_start, _)| span_start < end);
+        let suffix_start = self.pos;
+        self.suffix();
+
+        let prefix: String = self.chars[start..=quote].iter().collect();
+        let suffix: String = self.chars[suffix_start..self.pos].iter().collect();
+        let text = format!("{}{}{}", prefix, &self.source[body_start..end], suffix);
+        // Only the prefix and suffix can be written in an alternative way
+        let offset = self.offset_of(start);
+        let token_end = self.spans[self.pos - 1].1.max(end);
+        let alternative = self.source[offset..body_start] != prefix
+            || self.source[end..token_end] != suffix
+            || self.has_trigraph(offset, body_start)
+            || self.has_trigraph(end, token_end);
+        self.push_token(TokenKind::String, start, text, alternative);
+        Ok(())
+    }
+
+    /// User-defined literal suffix
+    fn suffix(&mut self) {
+        if self.peek(0).is_some_and(is_identifier_start) {
+            self.identifier();
+        }
+    }
+
+    /// `#` opening a preprocessor line: take the directive name along, and after
+    /// `include` the header name
+    fn directive(&mut self, start: usize) {
+        while self.peek(0).is_some_and(|c| c.is_whitespace() && c != '\n') {
+            self.pos += 1;
+        }
+        // A GNU linemarker, `# 33 "file"`, does what `#line` does
+        if self.peek(0).is_some_and(|c| c.is_ascii_digit()) {
+            self.push_token(TokenKind::Directive, start, "line".to_string(), true);
+            return;
+        }
+        let name = if self.peek(0).is_some_and(is_identifier_start) {
+            self.identifier()
+        } else {
+            String::new()
+        };
+        self.push(TokenKind::Directive, start, name.clone());
+
+        if !matches!(name.as_str(), "include" | "include_next" | "import") {
+            return;
+        }
+        while self.peek(0).is_some_and(|c| c.is_whitespace() && c != '\n') {
+            self.pos += 1;
+        }
+        let close = match self.peek(0) {
+            Some('<') => '>',
+            Some('"') => '"',
+            _ => return,
+        };
+        let header_start = self.pos;
+        self.pos += 1;
+        while self.peek(0).is_some_and(|c| c != close && c != '\n') {
+            self.pos += 1;
+        }
+        if self.peek(0) == Some(close) {
+            self.pos += 1;
+            let text = self.text(header_start);
+            self.push(TokenKind::HeaderName, header_start, text);
+        } else {
+            // Not a header name after all, lex it as ordinary tokens
+            self.pos = header_start;
+        }
+    }
+}
+
+fn is_identifier_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_' || c == '$'
+}
+
+fn is_identifier_continue(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '$'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lex(source: &str, trigraphs: bool) -> Vec<Token> {
+        tokenize(source, LexOptions { trigraphs }).unwrap()
+    }
+
+    fn texts(tokens: &[Token]) -> Vec<&str> {
+        tokens.iter().map(|token| token.text.as_str()).collect()
+    }
+
+    #[test]
+    fn digraphs() {
+        let tokens = lex("a <% b %> c<:0:> x %:%: y", false);
+        assert_eq!(
+            texts(&tokens),
+            ["a", "{", "b", "}", "c", "[", "0", "]", "x", "##", "y"]
+        );
+        assert!(tokens[1].alternative && tokens[9].alternative);
+        assert!(!tokens[0].alternative);
+        assert_eq!(tokens[1].spelling, "<%");
+    }
+
+    #[test]
+    fn less_colon_colon() {
+        assert_eq!(
+            texts(&lex("v<::std::string>", false)),
+            ["v", "<", "::", "std", "::", "string", ">"]
+        );
+        // Followed by `:` or `>`, `<:` is a digraph after all
+        assert_eq!(texts(&lex("a<::>", false)), ["a", "[", "]"]);
+        assert_eq!(texts(&lex("a<:::b", false)), ["a", "[", "::", "b"]);
+    }
+
+    #[test]
+    fn trigraphs_are_plain_characters_by_default() {
+        let tokens = lex(r#""??/"; system("id"); //""#, false);
+        assert_eq!(
+            texts(&tokens),
+            [r#""??/""#, ";", "system", "(", r#""id""#, ")", ";", r#"//""#]
+        );
+        assert!(tokens[0].alternative);
+        assert!(!tokens[2].alternative);
+
+        let tokens = lex("a ??= b", false);
+        assert_eq!(texts(&tokens), ["a", "?", "?", "=", "b"]);
+        assert!(tokens[1..4].iter().all(|token| token.alternative));
+    }
+
+    #[test]
+    fn trigraphs_when_enabled() {
+        let tokens = lex(r#""??/"; system("id"); //""#, true);
+        // `id` is a literal suffix here, so no `system` identifier is left
+        assert_eq!(texts(&tokens), [r#""\"; system("id"#, r#""); //""#]);
+        assert!(tokens[0].alternative);
+        assert_eq!(tokens[0].spelling, r#""??/"; system("id"#);
+
+        let tokens = lex("??=define X ??< ??>", true);
+        assert_eq!(tokens[0].kind, TokenKind::Directive);
+        assert_eq!(texts(&tokens), ["define", "X", "{", "}"]);
+    }
+
+    #[test]
+    fn universal_character_names() {
+        let tokens = lex(r"\u0073ystem caf\u00e9 \U0001F600", false);
+        assert_eq!(tokens[0].text, "system");
+        assert_eq!(tokens[0].spelling, r"\u0073ystem");
+        assert_eq!(tokens[0].kind, TokenKind::Identifier);
+        assert!(tokens[0].alternative);
+        assert_eq!(tokens[1].text, "caf\u{e9}");
+        assert_eq!(tokens[2].text, "\u{1F600}");
+        assert!(!lex("system", false)[0].alternative);
+    }
+
+    #[test]
+    fn line_splices() {
+        let tokens = lex("sys\\\ntem(1);\n#def\\\r\nine X", false);
+        assert_eq!(tokens[0].text, "system");
+        assert_eq!(tokens[0].spelling, "sys\\\ntem");
+        assert_eq!((tokens[0].line, tokens[0].column), (1, 1));
+        assert!(tokens[0].alternative);
+        assert_eq!(tokens[5].kind, TokenKind::Directive);
+        assert_eq!(tokens[5].text, "define");
+        assert_eq!((tokens[6].text.as_str(), tokens[6].line), ("X", 4));
+    }
+
+    #[test]
+    fn raw_strings() {
+        let source = "x = R\"d(a )\" ??/\nb\\\n)d\"_s; y";
+        for trigraphs in [false, true] {
+            let tokens = lex(source, trigraphs);
+            assert_eq!(
+                texts(&tokens),
+                ["x", "=", &source[4..source.len() - 3], ";", "y"]
+            );
+            assert_eq!(tokens[2].kind, TokenKind::String);
+            assert!(!tokens[2].alternative);
+            assert_eq!(tokens[4].offset, source.len() - 1);
+            assert_eq!(tokens[4].line, 3);
+        }
+
+        assert_eq!(lex("u8R\"(a)\"", false)[0].text, "u8R\"(a)\"");
+        assert!(tokenize("R\"(a)", LexOptions::default()).is_err());
+        assert!(tokenize("R\"a b(x)a b\"", LexOptions::default()).is_err());
+    }
+
+    #[test]
+    fn percent_colon_directives() {
+        let tokens = lex("  %: include <cstdio>\nint x; %:define", false);
+        assert_eq!(tokens[0].kind, TokenKind::Directive);
+        assert_eq!(tokens[0].text, "include");
+        assert!(tokens[0].alternative);
+        assert_eq!(tokens[1].kind, TokenKind::HeaderName);
+        assert_eq!(tokens[1].text, "<cstdio>");
+        // Not at the start of a line, so just a `#` punctuator
+        assert_eq!(tokens[5].kind, TokenKind::Punctuator);
+        assert_eq!(tokens[5].text, "#");
+    }
+
+    #[test]
+    fn linemarkers_are_line_directives() {
+        let tokens = lex("# 33 \"x.h\" 1\n#line 2", false);
+        assert_eq!(tokens[0].kind, TokenKind::Directive);
+        assert_eq!(tokens[0].text, "line");
+        assert!(tokens[0].alternative);
+        assert_eq!(texts(&tokens[1..]), ["33", "\"x.h\"", "1", "line", "2"]);
+        assert!(!tokens[4].alternative);
+    }
+}
@@ -0,0 +1,7 @@
+pub mod ast;
+pub mod lexer;
+pub mod preprocess;
+
+pub use ast::{Ast, AstNode};
+pub use lexer::{tokenize, LexOptions, Token, TokenKind};
+pub use preprocess::{line_markers, main_code, LineMarker};
@@ -0,0 +1,174 @@
+use regex::Regex;
+use std::sync::LazyLock;
+
+/// `# 12 "file" 1 3` line marker in GCC/Clang `-E` output
+static MARKER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"^# (\d+) "((?:[^"\\]|\\.)*)"((?: \d+)*)\s*$"#).unwrap());
+
+/// Where the following lines of preprocessor output came from
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LineMarker {
+    /// 1-based line of the marker itself in the output
+    pub output_line: usize,
+    /// Line in `file` of the output line after the marker
+    pub line: usize,
+    pub file: String,
+    /// 1 entering an include, 2 returning from one, 3 system header, 4 `extern "C"`
+    pub flags: Vec<u32>,
+    /// Renames the current file without entering or leaving one, which only a `#line`
+    /// directive or GNU linemarker in the source does
+    pub directive: bool,
+}
+
+/// A line of preprocessor output
+enum Line<'a> {
+    Marker(LineMarker),
+    /// Code, and whether it came from the compiled file rather than an included one
+    Code(&'a str, bool),
+}
+
+/// Follows include depth through the output. Only the push and pop flags are trusted:
+/// the file names in markers can be set from the source with `#line`.
+#[derive(Default)]
+struct Walker {
+    depth: usize,
+    /// The compiled file's own lines have started, after the `<built-in>` and
+    /// `<command-line>` preamble
+    started: bool,
+    in_preamble: bool,
+    file: Option<String>,
+}
+
+impl Walker {
+    fn line<'a>(&mut self, index: usize, text: &'a str) -> Line<'a> {
+        let Some(captures) = MARKER.captures(text) else {
+            let main = self.started && self.depth == 0 && !text.trim().is_empty();
+            return Line::Code(text, main);
+        };
+
+        let file = unescape(&captures[2]);
+        let flags: Vec<u32> = captures[3]
+            .split_whitespace()
+            .filter_map(|flag| flag.parse().ok())
+            .collect();
+        let pushes = flags.contains(&1);
+        let pops = flags.contains(&2);
+        if pushes {
+            self.depth += 1;
+        } else if pops {
+            self.depth = self.depth.saturating_sub(1);
+        }
+
+        let started = self.started;
+        if !started {
+            if file.starts_with('<') {
+                self.in_preamble = true;
+            } else if self.in_preamble && self.depth == 0 {
+                self.started = true;
+            }
+        }
+        let directive = started
+            && !pushes
+            && !pops
+            && self.file.as_ref().is_some_and(|current| *current != file);
+        self.file = Some(file.clone());
+
+        Line::Marker(LineMarker {
+            output_line: index + 1,
+            line: captures[1].parse().unwrap_or(0),
+            file,
+            flags,
+            directive,
+        })
+    }
+}
+
+/// Line markers of preprocessor output, in order
+pub fn line_markers(expanded: &str) -> Vec<LineMarker> {
+    let mut walker = Walker::default();
+    expanded
+        .lines()
+        .enumerate()
+        .filter_map(|(index, text)| match walker.line(index, text) {
+            Line::Marker(marker) => Some(marker),
+            Line::Code(..) => None,
+        })
+        .collect()
+}
+
+/// Output lines of the compiled file itself, without included headers, markers or blank
+/// lines. A `#line` in the source can't hide code, but a GNU linemarker claiming to enter
+/// an include can, so sources should be checked for `line` directives.
+pub fn main_code(expanded: &str) -> String {
+    let mut walker = Walker::default();
+    let mut code = String::new();
+    for (index, text) in expanded.lines().enumerate() {
+        if let Line::Code(text, true) = walker.line(index, text) {
+            code.push_str(text);
+            code.push('\n');
+        }
+    }
+    code
+}
+
+/// File names in markers are quoted like string literals
+fn unescape(quoted: &str) -> String {
+    let mut text = String::with_capacity(quoted.len());
+    let mut chars = quoted.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => text.extend(chars.next()),
+            c => text.push(c),
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `g++ -E` output for a source that includes a header and then uses `#line`
+    const GCC: &str = r#"# 0 "main.cpp"
+# 0 "<built-in>"
+# 0 "<command-line>"
+# 1 "/usr/include/stdc-predef.h" 1 3 4
+# 0 "<command-line>" 2
+# 1 "main.cpp"
+# 1 "/usr/include/x.h" 1 3 4
+int header();
+# 2 "main.cpp" 2
+int main() {
+# 1 "x.h"
+  system("id");
+}
+"#;
+
+    /// `clang -E` marks its preamble with flags instead
+    const CLANG: &str = r#"# 1 "main.cpp"
+# 1 "<built-in>" 1
+# 1 "<built-in>" 3
+int builtin;
+# 1 "<command line>" 1
+# 1 "<built-in>" 2
+# 1 "main.cpp" 2
+int main() {}
+"#;
+
+    #[test]
+    fn main_code_ignores_renamed_files() {
+        assert_eq!(main_code(GCC), "int main() {\n  system(\"id\");\n}\n");
+        assert_eq!(main_code(CLANG), "int main() {}\n");
+    }
+
+    #[test]
+    fn markers_report_line_directives() {
+        let markers = line_markers(GCC);
+        assert_eq!(markers.len(), 9);
+        assert_eq!(markers[6].flags, [1, 3, 4]);
+        assert!(!markers[7].directive);
+        assert_eq!(markers[8].file, "x.h");
+        assert!(markers[8].directive);
+        assert!(!line_markers(CLANG).iter().any(|marker| marker.directive));
+    }
+}
@@ -0,0 +1,340 @@
+use anyhow::{anyhow, Result};
+use regex::Regex;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::auth::Account;
+use crate::config::SolveConfig;
+
+/// How long a token CTFd accepted is trusted without asking again
+const TOKEN_TTL: Duration = Duration::from_secs(60);
+
+/// Verified tokens kept at most; expired ones are dropped first when full
+const MAX_CACHED_TOKENS: usize = 10_000;
+
+/// Client for a CTFd instance, used to validate player tokens and record solves
+pub struct CtfdClient {
+    url: String,
+    /// Admin API token, required to record submissions on behalf of players
+    secret: Option<String>,
+    http: reqwest::Client,
+    /// Accounts of recently verified tokens, keyed by the token's hash
+    verified: Mutex<HashMap<[u8; 32], (Instant, Account)>>,
+}
+
+/// Envelope of every CTFd API response
+#[derive(Deserialize)]
+struct ApiResponse<T> {
+    success: bool,
+    data: Option<T>,
+}
+
+#[derive(Deserialize)]
+struct Me {
+    id: u64,
+    name: String,
+    team_id: Option<u64>,
+}
+
+impl CtfdClient {
+    pub fn new(url: &str, secret: Option<String>) -> Result<Self> {
+        let http = reqwest::Client::builder()
+            .timeout(std::time::Duration::from_secs(10))
+            .build()?;
+
+        Ok(Self {
+            url: url.trim_end_matches('/').to_string(),
+            secret,
+            http,
+            verified: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Resolve a player's CTFd access token to their account, `None` if CTFd rejects it.
+    /// Accepted tokens are remembered for `TOKEN_TTL`.
+    pub async fn verify(&self, token: &str) -> Result<Option<Account>> {
+        let key: [u8; 32] = Sha256::digest(token.as_bytes()).into();
+        if let Some((verified, account)) = self.verified.lock().unwrap().get(&key) {
+            if verified.elapsed() < TOKEN_TTL {
+                return Ok(Some(account.clone()));
+            }
+        }
+
+        let account = self.fetch_account(token).await?;
+        if let Some(account) = &account {
+            let mut verified = self.verified.lock().unwrap();
+            if verified.len() >= MAX_CACHED_TOKENS {
+                verified.retain(|_, (at, _)| at.elapsed() < TOKEN_TTL);
+                if verified.len() >= MAX_CACHED_TOKENS {
+                    verified.clear();
+                }
+            }
+            verified.insert(key, (Instant::now(), account.clone()));
+        }
+        Ok(account)
+    }
+
+    async fn fetch_account(&self, token: &str) -> Result<Option<Account>> {
+        let response = self
+            .http
+            .get(format!("{}/api/v1/users/me", self.url))
+            .header("Authorization", format!("Token {}", token))
+            .header("Content-Type", "application/json")
+            .send()
+            .await
+            .map_err(|e| anyhow!("CTFd is unreachable: {}", e))?;
+
+        let status = response.status();
+        if status == reqwest::StatusCode::UNAUTHORIZED || status == reqwest::StatusCode::FORBIDDEN {
+            return Ok(None);
+        }
+        if !status.is_success() {
+            return Err(anyhow!("CTFd token check failed with status {}", status));
+        }
+
+        let body: ApiResponse<Me> = response
+            .json()
+            .await
+            .map_err(|e| anyhow!("Unexpected CTFd response: {}", e))?;
+        let Some(me) = body.data.filter(|_| body.success) else {
+            return Ok(None);
+        };
+
+        // In team mode solves belong to the team, otherwise to the user
+        let team = match me.team_id {
+            Some(team_id) => format!("team-{}", team_id),
+            None => format!("user-{}", me.id),
+        };
+        Ok(Some(Account {
+            team,
+            name: Some(me.name),
+            user_id: Some(me.id),
+            team_id: me.team_id,
+        }))
+    }
+
+    /// Record a correct submission for the account
+    pub async fn report_solve(
+        &self,
+        challenge_id: u64,
+        account: &Account,
+        provided: &str,
+    ) -> Result<()> {
+        let secret = self
+            .secret
+            .as_ref()
+            .ok_or_else(|| anyhow!("No CTFd secret is configured"))?;
+        let user_id = account
+            .user_id
+            .ok_or_else(|| anyhow!("Team {} has no CTFd account", account.team))?;
+
+        let response = self
+            .http
+            .post(format!("{}/api/v1/submissions", self.url))
+            .header("Authorization", format!("Token {}", secret))
+            .json(&serde_json::json!({
+                "challenge_id": challenge_id,
+                "user_id": user_id,
+                "team_id": account.team_id,
+                "provided": provided,
+                "type": "correct",
+            }))
+            .send()
+            .await
+            .map_err(|e| anyhow!("CTFd is unreachable: {}", e))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(anyhow!("CTFd rejected the solve ({}): {}", status, body));
+        }
+        Ok(())
+    }
+}
+
+/// Detects solves in `check` output and remembers which teams already solved
+pub struct SolveTracker {
+    challenge_id: Option<u64>,
+    markers: Vec<String>,
+    flag_pattern: Option<Regex>,
+    ctfd: Option<Arc<CtfdClient>>,
+    solved: Mutex<HashSet<String>>,
+}
+
+impl SolveTracker {
+    pub fn new(config: &SolveConfig, ctfd: Option<Arc<CtfdClient>>) -> Result<Self> {
+        let flag_pattern = match &config.flag_pattern {
+            Some(pattern) => Some(
+                Regex::new(pattern).map_err(|e| anyhow!("Invalid solve flag pattern: {}", e))?,
+            ),
+            None => None,
+        };
+
+        Ok(Self {
+            challenge_id: config.challenge_id,
+            markers: config.markers.clone(),
+            flag_pattern,
+            ctfd,
+            solved: Mutex::new(HashSet::new()),
+        })
+    }
+
+    /// The part of a successful `check` output that proves a solve, if any
+    fn solution<'a>(&self, output: &'a str) -> Option<&'a str> {
+        if let Some(found) = self.flag_pattern.as_ref().and_then(|re| re.find(output)) {
+            return Some(found.as_str());
+        }
+        self.markers
+            .iter()
+            .find(|marker| output.contains(marker.as_str()))
+            .map(|_| output)
+    }
+
+    /// Whether a successful `check` output counts as a solve
+    pub fn is_solve(&self, output: &str) -> bool {
+        self.solution(output).is_some()
+    }
+
+    /// Record a solve when `output` contains a marker or the flag.
+    ///
+    /// Only a team's first solve is forwarded to CTFd, when configured. A failed report is
+    /// forgotten so the next correct submission retries it.
+    pub async fn observe(&self, account: &Account, output: &str) {
+        let Some(provided) = self.solution(output) else {
+            return;
+        };
+        if !self.solved.lock().unwrap().insert(account.team.clone()) {
+            return;
+        }
+
+        if let (Some(ctfd), Some(challenge_id)) = (&self.ctfd, self.challenge_id) {
+            if let Err(err) = ctfd.report_solve(challenge_id, account, provided).await {
+                eprintln!("Failed to report solve for {}: {}", account.team, err);
+                self.solved.lock().unwrap().remove(&account.team);
+                return;
+            }
+        }
+
+        println!("Solve: {}", account.team);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        extract::State,
+        http::{HeaderMap, StatusCode},
+        routing::{get, post},
+        Json, Router,
+    };
+    use serde_json::{json, Value};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// What the mock CTFd saw
+    #[derive(Default)]
+    struct Mock {
+        token_checks: AtomicUsize,
+        submissions: Mutex<Vec<Value>>,
+    }
+
+    fn authorization(headers: &HeaderMap) -> &str {
+        headers
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+    }
+
+    async fn users_me(
+        State(mock): State<Arc<Mock>>,
+        headers: HeaderMap,
+    ) -> (StatusCode, Json<Value>) {
+        mock.token_checks.fetch_add(1, Ordering::SeqCst);
+        match authorization(&headers) {
+            "Token player" => (
+                StatusCode::OK,
+                Json(
+                    json!({ "success": true, "data": { "id": 7, "name": "alice", "team_id": 3 } }),
+                ),
+            ),
+            _ => (StatusCode::UNAUTHORIZED, Json(json!({ "success": false }))),
+        }
+    }
+
+    async fn submissions(
+        State(mock): State<Arc<Mock>>,
+        headers: HeaderMap,
+        Json(body): Json<Value>,
+    ) -> StatusCode {
+        if authorization(&headers) != "Token admin" {
+            return StatusCode::FORBIDDEN;
+        }
+        mock.submissions.lock().unwrap().push(body);
+        StatusCode::OK
+    }
+
+    /// Start a mock CTFd on a free port, returning its URL
+    async fn mock_ctfd() -> (String, Arc<Mock>) {
+        let mock = Arc::new(Mock::default());
+        let app = Router::new()
+            .route("/api/v1/users/me", get(users_me))
+            .route("/api/v1/submissions", post(submissions))
+            .with_state(mock.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (url, mock)
+    }
+
+    #[tokio::test]
+    async fn verifies_tokens_and_remembers_them() {
+        let (url, mock) = mock_ctfd().await;
+        let client = CtfdClient::new(&url, None).unwrap();
+
+        let account = client.verify("player").await.unwrap().unwrap();
+        assert_eq!(account.team, "team-3");
+        assert_eq!(account.name.as_deref(), Some("alice"));
+        assert_eq!((account.user_id, account.team_id), (Some(7), Some(3)));
+
+        client.verify("player").await.unwrap().unwrap();
+        assert_eq!(mock.token_checks.load(Ordering::SeqCst), 1);
+
+        assert!(client.verify("stolen").await.unwrap().is_none());
+        assert!(client.verify("stolen").await.unwrap().is_none());
+        assert_eq!(mock.token_checks.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn reports_each_team_once() {
+        let (url, mock) = mock_ctfd().await;
+        let client = Arc::new(CtfdClient::new(&url, Some("admin".to_string())).unwrap());
+        let config = SolveConfig {
+            challenge_id: Some(5),
+            markers: Vec::new(),
+            flag_pattern: Some(r"flag\{\w+\}".to_string()),
+        };
+        let tracker = SolveTracker::new(&config, Some(client.clone())).unwrap();
+        let account = client.verify("player").await.unwrap().unwrap();
+
+        tracker.observe(&account, "no luck").await;
+        tracker.observe(&account, "here: flag{abc}").await;
+        tracker.observe(&account, "again: flag{abc}").await;
+
+        let submissions = mock.submissions.lock().unwrap();
+        assert_eq!(
+            *submissions,
+            [json!({
+                "challenge_id": 5,
+                "user_id": 7,
+                "team_id": 3,
+                "provided": "flag{abc}",
+                "type": "correct",
+            })]
+        );
+    }
+}
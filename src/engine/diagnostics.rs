@@ -0,0 +1,159 @@
+use colored::Colorize;
+use rune::ast::Spanned;
+use rune::diagnostics::{Diagnostic as RuneDiagnostic, FatalDiagnosticKind};
+use rune::{SourceId, Sources};
+use serde::Serialize;
+use std::fmt;
+
+/// Severity of a script diagnostic
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// Byte range inside the script source
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// A single compile diagnostic, detached from the Rune sources it came from
+#[derive(Clone, Debug, Serialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub file: Option<String>,
+    pub span: Option<Span>,
+    /// 1-based line of the span start
+    pub line: Option<usize>,
+    /// 1-based column of the span start
+    pub column: Option<usize>,
+}
+
+/// Error returned when the Rune script fails to compile
+#[derive(Debug, Serialize)]
+pub struct CompileError {
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let errors = self
+            .diagnostics
+            .iter()
+            .filter(|d| d.severity == Severity::Error)
+            .count();
+        write!(f, "Rune script failed to compile with {} error(s)", errors)
+    }
+}
+
+impl std::error::Error for CompileError {}
+
+/// Convert Rune diagnostics into serializable diagnostics
+pub fn collect(diagnostics: &rune::Diagnostics, sources: &Sources) -> Vec<Diagnostic> {
+    diagnostics
+        .diagnostics()
+        .iter()
+        .filter_map(|diagnostic| match diagnostic {
+            RuneDiagnostic::Fatal(fatal) => {
+                let span = match fatal.kind() {
+                    FatalDiagnosticKind::CompileError(error) => Some(error.span()),
+                    _ => None,
+                };
+                Some(locate(
+                    Severity::Error,
+                    fatal.to_string(),
+                    fatal.source_id(),
+                    span,
+                    sources,
+                ))
+            }
+            RuneDiagnostic::Warning(warning) => Some(locate(
+                Severity::Warning,
+                warning.to_string(),
+                warning.source_id(),
+                Some(warning.span()),
+                sources,
+            )),
+            RuneDiagnostic::RuntimeWarning(warning) => Some(Diagnostic {
+                severity: Severity::Warning,
+                message: warning.to_string(),
+                file: None,
+                span: None,
+                line: None,
+                column: None,
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+fn locate(
+    severity: Severity,
+    message: String,
+    source_id: SourceId,
+    span: Option<rune::ast::Span>,
+    sources: &Sources,
+) -> Diagnostic {
+    let source = sources.get(source_id);
+    let file = source.map(|source| match source.path() {
+        Some(path) => path.to_string_lossy().to_string(),
+        None => source.name().to_string(),
+    });
+    let span = span.map(|span| Span {
+        start: span.start.into_usize(),
+        end: span.end.into_usize(),
+    });
+    let (line, column) = match (source, span) {
+        (Some(source), Some(span)) => {
+            let (line, column) = source.pos_to_utf8_linecol(span.start);
+            (Some(line + 1), Some(column + 1))
+        }
+        _ => (None, None),
+    };
+
+    Diagnostic {
+        severity,
+        message,
+        file,
+        span,
+        line,
+        column,
+    }
+}
+
+/// Render diagnostics for a terminal, one `severity: message` block per entry
+pub fn render(diagnostics: &[Diagnostic], color: bool) -> String {
+    let mut output = String::new();
+
+    for diagnostic in diagnostics {
+        let label = match diagnostic.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        let label = match (color, diagnostic.severity) {
+            (false, _) => label.to_string(),
+            (true, Severity::Error) => label.red().bold().to_string(),
+            (true, Severity::Warning) => label.yellow().bold().to_string(),
+        };
+        output.push_str(&format!("{}: {}\n", label, diagnostic.message));
+
+        if let Some(file) = &diagnostic.file {
+            let location = match (diagnostic.line, diagnostic.column) {
+                (Some(line), Some(column)) => format!("{}:{}:{}", file, line, column),
+                _ => file.clone(),
+            };
+            let arrow = if color {
+                "-->".blue().bold().to_string()
+            } else {
+                "-->".to_string()
+            };
+            output.push_str(&format!("  {} {}\n", arrow, location));
+        }
+    }
+
+    output
+}
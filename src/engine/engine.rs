@@ -1,79 +1,394 @@
-use anyhow::{Error, Result};
-use rune::termcolor::{ColorChoice, StandardStream};
-use rune::{Diagnostics, Source, Sources, Value, Vm};
-use std::{path::Path, sync::Arc};
+use anyhow::Result;
+use rune::runtime::debug::DebugArgs;
+use rune::runtime::{Bytes, RuntimeContext};
+use rune::{Diagnostics, Source, Sources, Unit, Value, Vm};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::IsTerminal;
+use std::{
+    fmt,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use crate::compiler::Toolchains;
+use crate::config::ChallengeConfig;
+use crate::metrics;
+use crate::redact::Redactor;
+use crate::sandbox::{SandboxHandle, SandboxLimits, SandboxManager};
+
+use super::diagnostics::{self, CompileError, Diagnostic, Severity};
+
+/// What the compiler learned about the script, without running it
+#[derive(Serialize)]
+pub struct ScriptInfo {
+    pub diagnostics: Vec<Diagnostic>,
+    /// Function name to argument count, `None` when the script failed to compile
+    pub functions: Option<BTreeMap<String, usize>>,
+}
+
+/// Payload handed to `check` as its `user_input` argument
+pub enum UserInput {
+    /// Raw text, passed as a string
+    Text(String),
+    /// JSON or form data, passed as Rune objects
+    Structured(serde_json::Value),
+    /// Non UTF-8 body, passed as Rune `Bytes`
+    Bytes(Vec<u8>),
+}
+
+/// Per-call details exposed to the script through `ctx`
+#[derive(Clone, Debug, Default)]
+pub struct CallContext {
+    /// Working directory of the submission, if one was created
+    pub sandbox: Option<SandboxHandle>,
+    /// Toolchains behind `ctx.sandbox().compile(..)`
+    pub toolchains: Option<Arc<Toolchains>>,
+    /// Scrubs secrets from compiler output
+    pub redactor: Option<Arc<Redactor>>,
+    /// Authenticated team making the call
+    pub team: Option<String>,
+}
+
+/// Sandboxes and toolchains for calls made from the command line, set up the way `listen`
+/// does, so scripts that compile work the same outside the server
+pub struct LocalCalls {
+    sandboxes: SandboxManager,
+    toolchains: Arc<Toolchains>,
+    redactor: Arc<Redactor>,
+}
+
+impl LocalCalls {
+    /// Toolchains are set up on first use, so scripts that never compile don't need a
+    /// compiler. Runs are one-off, so there is no object cache or disk quota.
+    pub fn new(config: &ChallengeConfig, bucket: &Path) -> Result<Self> {
+        let mut sandboxes =
+            SandboxManager::new(SandboxManager::default_root(), SandboxLimits::default())?;
+        if let Some(template) = &config.sandbox.template {
+            sandboxes = sandboxes.with_template(bucket, template)?;
+        }
+        Ok(Self {
+            sandboxes,
+            toolchains: Arc::new(Toolchains::new(
+                config,
+                bucket,
+                &Toolchains::default_cache_dir(),
+                0,
+            )?),
+            redactor: Arc::new(Redactor::new(&config.redact, bucket)?),
+        })
+    }
+
+    /// Run `call` with a fresh sandbox, destroyed once it returns
+    pub fn run<T>(
+        &self,
+        team: Option<String>,
+        call: impl FnOnce(&CallContext) -> Result<T>,
+    ) -> Result<T> {
+        let sandbox = self.sandboxes.create_unmanaged()?;
+        let context = CallContext {
+            sandbox: Some(sandbox.handle(SandboxLimits::default())),
+            toolchains: Some(self.toolchains.clone()),
+            redactor: Some(self.redactor.clone()),
+            team,
+        };
+        let result = call(&context);
+        if let Err(err) = sandbox.destroy() {
+            eprintln!("Failed to cleanup sandbox: {}", err);
+        }
+        result
+    }
+}
+
+/// Error returned when calling an action the challenge config does not declare
+#[derive(Debug)]
+pub struct UnknownAction(pub String);
+
+impl fmt::Display for UnknownAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unknown action: {}", self.0)
+    }
+}
+
+impl std::error::Error for UnknownAction {}
 
 pub struct RuneEngine {
     script_path: String,
     data_directory: String,
+    config: Arc<ChallengeConfig>,
+    last_compile: Mutex<Option<LastCompile>>,
+}
+
+/// Outcome of the most recent compile, kept for readiness checks
+struct LastCompile {
+    /// Modification time of the script when it was read
+    modified: Option<SystemTime>,
+    status: Result<(), String>,
 }
 
 impl RuneEngine {
     pub async fn new(script_path: &Path, data_directory: &Path) -> Result<Self> {
         let script_path_str = script_path.to_string_lossy().to_string();
         let data_directory_str = data_directory.to_string_lossy().to_string();
+        let config = ChallengeConfig::load(data_directory)?;
 
         Ok(Self {
             script_path: script_path_str,
             data_directory: data_directory_str,
+            config: Arc::new(config),
+            last_compile: Mutex::new(None),
         })
     }
 
-    fn compile_vm(&self) -> Result<Vm> {
-        let mut rune_context = rune::Context::with_default_modules()?;
+    pub fn config(&self) -> &ChallengeConfig {
+        &self.config
+    }
 
-        rune_context.install(super::modules::context::module(true)?)?;
+    fn rune_context(&self) -> Result<rune::Context> {
+        let mut rune_context = rune::Context::with_default_modules()?;
+        rune_context.install(super::modules::module(true)?)?;
+        rune_context.install(super::modules::cpp::module()?)?;
+        rune_context.install(super::modules::binary::module()?)?;
+        Ok(rune_context)
+    }
 
-        // Compile script
+    /// Compile script, returning the unit (if any) along with every diagnostic
+    fn compile_unit(
+        &self,
+        rune_context: &rune::Context,
+    ) -> Result<(Option<Unit>, Vec<Diagnostic>)> {
         let mut sources = Sources::new();
         let mut diagnostics = Diagnostics::new();
         sources.insert(Source::from_path(&self.script_path)?)?;
 
         let unit = rune::prepare(&mut sources)
-            .with_context(&rune_context)
+            .with_context(rune_context)
             .with_diagnostics(&mut diagnostics)
             .build();
 
-        if !diagnostics.is_empty() {
-            let mut writer = StandardStream::stderr(ColorChoice::Always);
-            diagnostics.emit(&mut writer, &sources)?;
+        Ok((unit.ok(), diagnostics::collect(&diagnostics, &sources)))
+    }
+
+    /// Compile script without running it and report its diagnostics and functions
+    pub fn inspect(&self) -> Result<ScriptInfo> {
+        let rune_context = self.rune_context()?;
+        let (unit, diagnostics) = self.compile_unit(&rune_context)?;
+
+        let functions = unit.map(|unit| {
+            unit.debug_info()
+                .map(|debug| {
+                    debug
+                        .functions
+                        .values()
+                        .map(|signature| {
+                            let arity = match &signature.args {
+                                DebugArgs::EmptyArgs => 0,
+                                DebugArgs::TupleArgs(n) => *n,
+                                DebugArgs::Named(args) => args.len(),
+                            };
+                            (signature.path.to_string(), arity)
+                        })
+                        .collect()
+                })
+                .unwrap_or_default()
+        });
+
+        Ok(ScriptInfo {
+            diagnostics,
+            functions,
+        })
+    }
+
+    fn script_modified(&self) -> Option<SystemTime> {
+        std::fs::metadata(&self.script_path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    }
+
+    /// Whether the script on disk compiles, reusing the outcome of the last compile unless
+    /// the file changed since
+    pub fn compile_status(&self) -> Result<(), String> {
+        let modified = self.script_modified();
+        if let Some(last) = &*self.last_compile.lock().unwrap() {
+            if modified.is_some() && last.modified == modified {
+                return last.status.clone();
+            }
+        }
+        self.compile().map(|_| ()).map_err(|err| err.to_string())
+    }
+
+    /// Compile script once, so it can be run against many inputs. Warnings are printed
+    /// the first time each version of the script compiles, not on every call.
+    pub fn compile(&self) -> Result<CompiledScript> {
+        let modified = self.script_modified();
+        let result = self.compile_script();
+        let status = result.as_ref().map(|_| ()).map_err(|err| err.to_string());
+        let previous = self
+            .last_compile
+            .lock()
+            .unwrap()
+            .replace(LastCompile { modified, status });
+
+        let seen = modified.is_some() && previous.is_some_and(|last| last.modified == modified);
+        if let Ok(script) = &result {
+            if !seen && !script.diagnostics.is_empty() {
+                let stderr = std::io::stderr();
+                eprint!(
+                    "{}",
+                    diagnostics::render(&script.diagnostics, stderr.is_terminal())
+                );
+            }
         }
+        result
+    }
+
+    fn compile_script(&self) -> Result<CompiledScript> {
+        let started = std::time::Instant::now();
+        let rune_context = self.rune_context()?;
+        let (unit, diagnostics) = self.compile_unit(&rune_context)?;
+
+        let unit = match unit {
+            Some(unit) if !diagnostics.iter().any(|d| d.severity == Severity::Error) => unit,
+            _ => {
+                metrics::COMPILE_DURATION
+                    .with_label_values(&["error"])
+                    .observe(started.elapsed().as_secs_f64());
+                return Err(CompileError { diagnostics }.into());
+            }
+        };
+        metrics::COMPILE_DURATION
+            .with_label_values(&["ok"])
+            .observe(started.elapsed().as_secs_f64());
 
-        let unit = unit?;
-        let runtime = rune_context.runtime()?;
-        Ok(Vm::new(Arc::new(runtime), Arc::new(unit)))
+        Ok(CompiledScript {
+            runtime: Arc::new(rune_context.runtime()?),
+            unit: Arc::new(unit),
+            data_directory: self.data_directory.clone(),
+            config: self.config.clone(),
+            diagnostics,
+        })
     }
 
     pub async fn call_collect(&self) -> Result<Result<String, String>> {
-        let mut vm = self.compile_vm()?;
-        let ctx = super::modules::context::Context::new(self.data_directory.clone());
-        let output = vm.call(["collect"], (ctx,))?;
-        self.process_result(output)
+        self.compile()?.call_collect()
     }
 
-    pub async fn call_check(&self, user_input: &str) -> Result<Result<String, String>> {
-        let mut vm = self.compile_vm()?;
+    pub async fn call_check_with(
+        &self,
+        user_input: &UserInput,
+        call: &CallContext,
+    ) -> Result<Result<String, String>> {
+        self.compile()?.call_check_with(user_input, call)
+    }
+}
+
+/// A compiled script bound to its data bucket
+pub struct CompiledScript {
+    runtime: Arc<RuntimeContext>,
+    unit: Arc<Unit>,
+    data_directory: String,
+    config: Arc<ChallengeConfig>,
+    /// Warnings the script compiled with
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl CompiledScript {
+    fn vm(&self) -> Vm {
+        Vm::new(self.runtime.clone(), self.unit.clone())
+    }
+
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    pub fn call_collect(&self) -> Result<Result<String, String>> {
         let ctx = super::modules::context::Context::new(self.data_directory.clone());
-        let output = vm.call(["check"], (ctx, user_input))?;
-        self.process_result(output)
-    }
-
-    fn process_result(&self, value: Value) -> Result<Result<String, String>> {
-        // Try to extract value from Result type
-        match rune::from_value::<Result<Value, Value>>(value.clone()) {
-            // rune returns Result
-            Ok(result) => match result {
-                // rune script successfully returns a success value
-                Ok(success_value) => Ok(Ok(rune_value_throw_or_stringify(success_value)?)),
-                // rune script successfully returns a error value
-                Err(error_value) => Ok(match rune::from_value::<String>(&error_value) {
-                    Ok(error_msg) => Err(error_msg),
-                    Err(_) => Err(rune_value_throw_or_stringify(error_value)?),
-                }),
-            },
-            // rune returns non Result, treat it as a success returned value
-            Err(_) => Ok(Ok(rune_value_throw_or_stringify(value)?)),
+        let entry = self.config.entry_points.collect.as_str();
+        let output = self.vm().call([entry], (ctx,))?;
+        process_result(output)
+    }
+
+    fn context(&self, call: &CallContext) -> super::modules::context::Context {
+        let mut ctx = super::modules::context::Context::new(self.data_directory.clone());
+        if let Some(sandbox) = &call.sandbox {
+            ctx = ctx.with_sandbox(
+                sandbox.clone(),
+                call.toolchains.clone(),
+                call.redactor.clone(),
+            );
+        }
+        if let Some(team) = &call.team {
+            ctx = ctx.with_team(team.clone());
+        }
+        ctx
+    }
+
+    /// Call `check` with a text or structured payload and per-call details
+    pub fn call_check_with(
+        &self,
+        user_input: &UserInput,
+        call: &CallContext,
+    ) -> Result<Result<String, String>> {
+        let ctx = self.context(call);
+
+        let user_input = match user_input {
+            UserInput::Text(text) => rune::to_value(text.as_str())?,
+            UserInput::Structured(value) => serde_json::from_value::<Value>(value.clone())?,
+            UserInput::Bytes(bytes) => rune::to_value(Bytes::try_from(bytes.clone())?)?,
+        };
+
+        let entry = self.config.entry_points.check.as_str();
+        let output = self.vm().call([entry], (ctx, user_input))?;
+        process_result(output)
+    }
+
+    /// Call a whitelisted action as `function(ctx, args...)`
+    pub fn call_action(
+        &self,
+        name: &str,
+        args: Vec<serde_json::Value>,
+        call: &CallContext,
+    ) -> Result<Result<String, String>> {
+        let (function, action) = self
+            .config
+            .action(name)
+            .ok_or_else(|| UnknownAction(name.to_string()))?;
+
+        if args.len() != action.args {
+            return Err(anyhow::anyhow!(
+                "Action `{}` takes {} argument(s), got {}",
+                name,
+                action.args,
+                args.len()
+            ));
+        }
+
+        let mut call_args = vec![rune::to_value(self.context(call))?];
+        for arg in args {
+            call_args.push(serde_json::from_value::<Value>(arg)?);
         }
+
+        let output = self.vm().call([function], call_args)?;
+        process_result(output)
+    }
+}
+
+fn process_result(value: Value) -> Result<Result<String, String>> {
+    // Try to extract value from Result type
+    match rune::from_value::<Result<Value, Value>>(value.clone()) {
+        // rune returns Result
+        Ok(result) => match result {
+            // rune script successfully returns a success value
+            Ok(success_value) => Ok(Ok(rune_value_throw_or_stringify(success_value)?)),
+            // rune script successfully returns a error value
+            Err(error_value) => Ok(match rune::from_value::<String>(&error_value) {
+                Ok(error_msg) => Err(error_msg),
+                Err(_) => Err(rune_value_throw_or_stringify(error_value)?),
+            }),
+        },
+        // rune returns non Result, treat it as a success returned value
+        Err(_) => Ok(Ok(rune_value_throw_or_stringify(value)?)),
     }
 }
 
@@ -1,4 +1,7 @@
+pub mod diagnostics;
+#[allow(clippy::module_inception)]
 pub mod engine;
 pub mod modules;
 
-pub use engine::RuneEngine;
+pub use diagnostics::CompileError;
+pub use engine::{CallContext, CompiledScript, LocalCalls, RuneEngine, UnknownAction, UserInput};
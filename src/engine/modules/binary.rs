@@ -0,0 +1,152 @@
+use rune::{Any, ContextError, Module};
+use std::io;
+use std::sync::Arc;
+
+use crate::binary::{self, MAX_BINARY_BYTES};
+use crate::engine::modules::context::SandboxDir;
+use crate::redact::Redactor;
+
+/// Inspection of compiled artifacts for jailbox scripts, without running them
+#[rune::module(::jailapi::binary)]
+pub fn module() -> Result<Module, ContextError> {
+    let mut module = Module::from_meta(self::module_meta)?;
+    module.ty::<Binary>()?;
+    module.ty::<Symbol>()?;
+    module.ty::<Section>()?;
+    module.function_meta(open)?;
+    module.function_meta(Binary::symbols)?;
+    module.function_meta(Binary::imports)?;
+    module.function_meta(Binary::sections)?;
+    module.function_meta(Binary::has_symbol)?;
+    module.function_meta(Binary::contains_string)?;
+    module.function_meta(Binary::strings)?;
+    Ok(module)
+}
+
+/// A parsed ELF file
+#[derive(Debug, Any)]
+#[rune(item = ::jailapi::binary)]
+pub struct Binary {
+    binary: binary::Binary,
+    redactor: Option<Arc<Redactor>>,
+    /// File size in bytes
+    #[rune(get)]
+    size: i64,
+}
+
+#[derive(Clone, Debug, Any)]
+#[rune(item = ::jailapi::binary)]
+pub struct Symbol {
+    /// As stored, mangled for C++
+    #[rune(get)]
+    name: String,
+    #[rune(get)]
+    demangled: Option<String>,
+    /// `text`, `data`, `tls` or `unknown`
+    #[rune(get)]
+    kind: String,
+    /// Section the symbol is defined in, `None` when it's undefined
+    #[rune(get)]
+    section: Option<String>,
+    #[rune(get)]
+    address: i64,
+    #[rune(get)]
+    size: i64,
+    #[rune(get)]
+    global: bool,
+}
+
+#[derive(Clone, Debug, Any)]
+#[rune(item = ::jailapi::binary)]
+pub struct Section {
+    #[rune(get)]
+    name: String,
+    /// `text`, `data`, `rodata`, `bss`, `tls`, `debug` or `other`
+    #[rune(get)]
+    kind: String,
+    #[rune(get)]
+    size: i64,
+}
+
+/// Parse an ELF file in the sandbox, such as the `binary` of a compile
+#[rune::function]
+pub fn open(sandbox: &SandboxDir, path: &str) -> Result<Binary, io::Error> {
+    let bytes = sandbox.read_artifact(path, MAX_BINARY_BYTES)?;
+    let binary = binary::Binary::parse(&bytes).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Failed to parse {}: {}", path, e),
+        )
+    })?;
+    Ok(Binary {
+        size: binary.size as i64,
+        binary,
+        redactor: sandbox.redactor(),
+    })
+}
+
+impl Binary {
+    /// Symbol table, or the dynamic symbol table of a stripped binary
+    #[rune::function]
+    pub fn symbols(&self) -> Vec<Symbol> {
+        self.binary
+            .symbols
+            .iter()
+            .map(|symbol| Symbol {
+                name: symbol.name.clone(),
+                demangled: symbol.demangled.clone(),
+                kind: symbol.kind.to_string(),
+                section: symbol.section.clone(),
+                address: symbol.address as i64,
+                size: symbol.size as i64,
+                global: symbol.global,
+            })
+            .collect()
+    }
+
+    /// Names the dynamic linker resolves from shared libraries, e.g. `system`
+    #[rune::function]
+    pub fn imports(&self) -> Vec<String> {
+        self.binary.imports.clone()
+    }
+
+    #[rune::function]
+    pub fn sections(&self) -> Vec<Section> {
+        self.binary
+            .sections
+            .iter()
+            .map(|section| Section {
+                name: section.name.clone(),
+                kind: section.kind.to_string(),
+                size: section.size as i64,
+            })
+            .collect()
+    }
+
+    /// Whether a symbol or import is named `name`, mangled, demangled, or as a bare
+    /// function name like `goal`
+    #[rune::function]
+    pub fn has_symbol(&self, name: &str) -> bool {
+        self.binary.has_symbol(name)
+    }
+
+    /// Whether `text` is among the binary's constants or initialised data
+    #[rune::function]
+    pub fn contains_string(&self, text: &str) -> bool {
+        self.binary.contains(text.as_bytes())
+    }
+
+    /// Printable strings of at least `min_len` characters in constants and initialised
+    /// data, with secrets masked
+    #[rune::function]
+    pub fn strings(&self, min_len: i64) -> Vec<String> {
+        let strings = self.binary.strings(min_len.max(0) as usize);
+        match &self.redactor {
+            Some(redactor) => strings
+                .iter()
+                .map(|string| redactor.scrub(string).0)
+                .collect(),
+            None => strings,
+        }
+    }
+}
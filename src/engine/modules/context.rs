@@ -1,16 +1,42 @@
-use rune::{Any, ContextError, Module};
+use rune::{Any, ContextError, Module, Value};
 use std::path::{Component, Path};
+use std::sync::Arc;
 use std::{fs, io, path::PathBuf};
 
+use crate::compiler::{self, AstOutput, Compiler, PreprocessOutput, Toolchains};
+use crate::cpp;
+use crate::redact::Redactor;
+use crate::sandbox::{self, SandboxHandle};
+use crate::template::{self, Rendered};
+
 /// Context module for jailbox, providing file operations
 #[rune::module(::jailapi::context)]
 pub fn module(_stdio: bool) -> Result<Module, ContextError> {
     let mut module = Module::from_meta(self::module_meta)?;
     module.ty::<Context>()?;
     module.ty::<DataBucket>()?;
+    module.ty::<SandboxDir>()?;
+    module.ty::<CompileOutput>()?;
+    module.ty::<RenderedSource>()?;
+    module.ty::<CompilerDiagnostic>()?;
+    module.function_meta(CompileOutput::diagnostics)?;
     module.function_meta(Context::bucket)?;
+    module.function_meta(Context::sandbox)?;
+    module.function_meta(Context::team)?;
     module.function_meta(DataBucket::read)?;
     module.function_meta(DataBucket::list)?;
+    module.function_meta(DataBucket::render)?;
+    module.function_meta(RenderedSource::source)?;
+    module.function_meta(RenderedSource::payload)?;
+    module.function_meta(SandboxDir::path)?;
+    module.function_meta(SandboxDir::read)?;
+    module.function_meta(SandboxDir::list)?;
+    module.function_meta(SandboxDir::copy_from_bucket)?;
+    module.function_meta(SandboxDir::compile)?;
+    module.function_meta(SandboxDir::compile_with)?;
+    module.function_meta(SandboxDir::toolchain)?;
+    module.ty::<Toolchain>()?;
+    module.function_meta(Toolchain::compile)?;
     Ok(module)
 }
 
@@ -18,6 +44,8 @@ pub fn module(_stdio: bool) -> Result<Module, ContextError> {
 #[rune(item = ::jailapi::context)]
 pub struct Context {
     bucket: DataBucket,
+    sandbox: Option<SandboxDir>,
+    team: Option<String>,
 }
 
 #[derive(Clone, Debug, Any)]
@@ -26,17 +54,428 @@ pub struct DataBucket {
     path: String,
 }
 
+/// Working directory of the current submission
+#[derive(Clone, Debug, Any)]
+#[rune(item = ::jailapi::context, name = Sandbox)]
+pub struct SandboxDir {
+    path: String,
+    bucket: DataBucket,
+    handle: SandboxHandle,
+    toolchains: Option<Arc<Toolchains>>,
+    redactor: Option<Arc<Redactor>>,
+}
+
+/// A toolchain profile bound to the sandbox it runs in
+#[derive(Clone, Debug, Any)]
+#[rune(item = ::jailapi::context)]
+pub struct Toolchain {
+    /// `None` for the default profile
+    profile: Option<String>,
+    sandbox: SandboxHandle,
+    toolchains: Arc<Toolchains>,
+    redactor: Option<Arc<Redactor>>,
+}
+
+/// Template rendered with a payload, remembering which parts the player wrote
+#[derive(Clone, Debug, Any)]
+#[rune(item = ::jailapi::context)]
+pub struct RenderedSource(Rendered);
+
+/// Outcome of `Sandbox::compile`
+#[derive(Debug, Any)]
+#[rune(item = ::jailapi::context)]
+pub struct CompileOutput {
+    #[rune(get)]
+    success: bool,
+    /// Compiler and linker diagnostics, with template source and secrets masked
+    #[rune(get)]
+    stderr: String,
+    /// Path of the executable relative to the sandbox, `None` when compilation failed
+    #[rune(get)]
+    binary: Option<String>,
+    /// Compilation was skipped because the same source was compiled before
+    #[rune(get)]
+    cached: bool,
+    #[rune(get)]
+    timed_out: bool,
+    diagnostics: Vec<CompilerDiagnostic>,
+}
+
+/// A compiler message re-anchored to the player's payload
+#[derive(Clone, Debug, Any)]
+#[rune(item = ::jailapi::context)]
+pub struct CompilerDiagnostic {
+    /// `error`, `fatal error`, `warning` or `note`
+    #[rune(get)]
+    severity: String,
+    #[rune(get)]
+    message: String,
+    /// Position in the payload, all `None` when the message points into the template
+    #[rune(get)]
+    line: Option<i64>,
+    #[rune(get)]
+    column: Option<i64>,
+    /// Byte offset into the payload
+    #[rune(get)]
+    offset: Option<i64>,
+}
+
 impl Context {
     pub fn new(bucket_path: String) -> Self {
         Context {
             bucket: DataBucket::new(bucket_path),
+            sandbox: None,
+            team: None,
         }
     }
 
+    pub fn with_team(mut self, team: String) -> Self {
+        self.team = Some(team);
+        self
+    }
+
+    pub fn with_sandbox(
+        mut self,
+        sandbox: SandboxHandle,
+        toolchains: Option<Arc<Toolchains>>,
+        redactor: Option<Arc<Redactor>>,
+    ) -> Self {
+        self.sandbox = Some(SandboxDir {
+            path: sandbox.path.to_string_lossy().to_string(),
+            bucket: self.bucket.clone(),
+            handle: sandbox,
+            toolchains,
+            redactor,
+        });
+        self
+    }
+
     #[rune::function]
     pub fn bucket(&self) -> DataBucket {
         self.bucket.clone()
     }
+
+    /// Authenticated team of the caller, `None` for anonymous calls
+    #[rune::function]
+    pub fn team(&self) -> Option<String> {
+        self.team.clone()
+    }
+
+    #[rune::function]
+    pub fn sandbox(&self) -> Result<SandboxDir, io::Error> {
+        self.sandbox.clone().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                "No sandbox is attached to this call",
+            )
+        })
+    }
+}
+
+impl SandboxDir {
+    #[rune::function]
+    pub fn path(&self) -> String {
+        self.path.clone()
+    }
+
+    /// Read a file in the sandbox. Paths are resolved first, so symlinks a compiled
+    /// program left behind can't point outside it.
+    #[rune::function]
+    pub fn read(&self, file_path: &str) -> Result<String, io::Error> {
+        let path = self.resolve(file_path)?;
+        fs::read_to_string(&path).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("Failed to read file {}: {}", file_path, e),
+            )
+        })
+    }
+
+    /// List a directory in the sandbox, resolved like `read`
+    #[rune::function]
+    pub fn list(&self, dpath: &str) -> Result<Vec<String>, io::Error> {
+        let path = self.resolve(dpath)?;
+        if !path.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("Directory not found: {}", dpath),
+            ));
+        }
+
+        Ok(fs::read_dir(&path)
+            .map_err(|e| io::Error::new(e.kind(), format!("Failed to read directory: {}", e)))?
+            .filter_map(Result::ok)
+            .map(|entry| entry.file_name().into_string().unwrap_or_default())
+            .collect::<Vec<_>>())
+    }
+
+    /// Canonical path of `file_path` in the sandbox, refusing anything that resolves
+    /// outside of it
+    fn resolve(&self, file_path: &str) -> Result<PathBuf, io::Error> {
+        let root = Path::new(&self.path).canonicalize()?;
+        let path = root.join(file_path).canonicalize().map_err(|e| {
+            io::Error::new(e.kind(), format!("Failed to open {}: {}", file_path, e))
+        })?;
+        if !path.starts_with(&root) {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("Access to this path is not allowed: {}", file_path),
+            ));
+        }
+        Ok(path)
+    }
+
+    /// Copy a bucket file or directory, read-only, to the same relative path in the sandbox
+    #[rune::function]
+    pub fn copy_from_bucket(&self, file_path: &str) -> Result<(), io::Error> {
+        let safe_path = normalize_path(file_path);
+
+        // Security check: ensure path is within data bucket directory
+        if !security_path_within(&safe_path, &self.bucket.path) {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("Access to this path is not allowed: {}", file_path),
+            ));
+        }
+
+        let relative = safe_path.strip_prefix("/").unwrap_or(&safe_path);
+        self.handle.reserve(sandbox::bucket_size(
+            Path::new(&self.bucket.path),
+            &relative.to_string_lossy(),
+        ))?;
+        sandbox::copy_from_bucket(
+            Path::new(&self.bucket.path),
+            &relative.to_string_lossy(),
+            &Path::new(&self.path).join(relative),
+        )
+        .map_err(|e| io::Error::new(e.kind(), format!("Failed to copy {}: {}", file_path, e)))
+    }
+
+    /// Compile C++ source, a string or a `RenderedSource`, to an executable in the sandbox
+    /// with the default toolchain profile. Render the payload with `bucket.render` to get
+    /// diagnostics located in it; a plain string is all treated as template.
+    #[rune::function]
+    pub fn compile(&self, source: Value) -> Result<CompileOutput, io::Error> {
+        self.bind_toolchain(None)?
+            .compile_source(&source_arg(&source)?, &[])
+    }
+
+    /// Compile with a named toolchain profile and extra flags the profile allows
+    #[rune::function]
+    pub fn compile_with(
+        &self,
+        source: Value,
+        profile: &str,
+        flags: Vec<String>,
+    ) -> Result<CompileOutput, io::Error> {
+        self.bind_toolchain(Some(profile))?
+            .compile_source(&source_arg(&source)?, &flags)
+    }
+
+    /// A toolchain profile bound to this sandbox, for `jailapi::cpp` functions
+    #[rune::function]
+    pub fn toolchain(&self, profile: &str) -> Result<Toolchain, io::Error> {
+        self.bind_toolchain(Some(profile))
+    }
+
+    fn bind_toolchain(&self, profile: Option<&str>) -> Result<Toolchain, io::Error> {
+        let toolchains = self.toolchains.clone().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                "No compiler is attached to this call",
+            )
+        })?;
+        // Fail on unknown profiles here rather than on first use
+        toolchains.check(profile)?;
+
+        Ok(Toolchain {
+            profile: profile.map(str::to_string),
+            sandbox: self.handle.clone(),
+            toolchains,
+            redactor: self.redactor.clone(),
+        })
+    }
+
+    /// Read a file the submission produced, relative to the sandbox like the `binary`
+    /// `compile` returns. Symlinks out of the sandbox are refused.
+    pub(crate) fn read_artifact(
+        &self,
+        file_path: &str,
+        max_bytes: u64,
+    ) -> Result<Vec<u8>, io::Error> {
+        let path = self.resolve(file_path)?;
+        let size = fs::metadata(&path)?.len();
+        if size > max_bytes {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{} is {} bytes, over the {} byte limit",
+                    file_path, size, max_bytes
+                ),
+            ));
+        }
+        fs::read(&path)
+    }
+
+    pub(crate) fn redactor(&self) -> Option<Arc<Redactor>> {
+        self.redactor.clone()
+    }
+}
+
+impl Toolchain {
+    fn compiler(&self) -> Result<Arc<Compiler>, io::Error> {
+        self.toolchains.get(self.profile.as_deref(), &self.sandbox)
+    }
+
+    /// Compile C++ source, a string or a `RenderedSource`, with this profile and extra
+    /// flags it allows
+    #[rune::function]
+    pub fn compile(&self, source: Value, flags: Vec<String>) -> Result<CompileOutput, io::Error> {
+        self.compile_source(&source_arg(&source)?, &flags)
+    }
+
+    fn compile_source(
+        &self,
+        source: &Rendered,
+        flags: &[String],
+    ) -> Result<CompileOutput, io::Error> {
+        let output = self
+            .compiler()?
+            .compile(&self.sandbox, &source.source, flags)
+            .map_err(|e| io::Error::new(e.kind(), format!("Failed to compile: {}", e)))?;
+
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let diagnostics = compiler::parse_diagnostics(&stderr)
+            .into_iter()
+            .map(|diagnostic| {
+                let position = (diagnostic.file == compiler::SOURCE_FILE)
+                    .then(|| source.payload_position(diagnostic.line, diagnostic.column))
+                    .flatten();
+                CompilerDiagnostic {
+                    severity: diagnostic.severity,
+                    message: self.scrub(&diagnostic.message),
+                    line: position.map(|p| p.line as i64),
+                    column: position.map(|p| p.column as i64),
+                    offset: position.map(|p| p.offset as i64),
+                }
+            })
+            .collect();
+
+        Ok(CompileOutput {
+            success: output.success,
+            stderr: self.redact_diagnostics(&stderr, source),
+            binary: output
+                .binary
+                .map(|path| path.to_string_lossy().into_owned()),
+            cached: output.cached,
+            timed_out: output.timed_out,
+            diagnostics,
+        })
+    }
+
+    /// How the toolchain's compiler reads source, for tokenizing it the same way
+    pub(crate) fn lex_options(&self) -> Result<cpp::LexOptions, io::Error> {
+        Ok(cpp::LexOptions {
+            trigraphs: self.compiler()?.trigraphs(),
+        })
+    }
+
+    /// Run the preprocessor over `source`, returning the expanded text with secrets masked
+    /// and diagnostics redacted
+    pub(crate) fn preprocess_source(
+        &self,
+        source: &Rendered,
+    ) -> Result<PreprocessOutput, io::Error> {
+        let mut output = self
+            .compiler()?
+            .preprocess(&self.sandbox, &source.source)
+            .map_err(|e| io::Error::new(e.kind(), format!("Failed to preprocess: {}", e)))?;
+        output.expanded = self.scrub(&output.expanded);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        output.stderr = self.redact_diagnostics(&stderr, source).into_bytes();
+        Ok(output)
+    }
+
+    /// Dump and simplify the Clang AST of `source`, with names scrubbed of secrets and
+    /// diagnostics redacted
+    pub(crate) fn ast_source(&self, source: &Rendered) -> Result<(cpp::Ast, AstOutput), io::Error> {
+        let clang = self.toolchains.clang()?;
+        let mut output = self
+            .compiler()?
+            .dump_ast(&self.sandbox, clang, &source.source)
+            .map_err(|e| io::Error::new(e.kind(), format!("Failed to dump AST: {}", e)))?;
+        if output.timed_out {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "Clang timed out dumping the AST",
+            ));
+        }
+
+        let mut ast = cpp::ast::parse(&output.json, compiler::SOURCE_FILE).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Failed to read AST dump: {}", e),
+            )
+        })?;
+        for node in &mut ast.nodes {
+            node.name = node.name.as_deref().map(|name| self.scrub(name));
+        }
+        output.json = Vec::new();
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        output.stderr = self.redact_diagnostics(&stderr, source).into_bytes();
+        Ok((ast, output))
+    }
+
+    fn scrub(&self, text: &str) -> String {
+        match &self.redactor {
+            Some(redactor) => redactor.scrub(text).0,
+            None => text.to_string(),
+        }
+    }
+
+    fn redact_diagnostics(&self, stderr: &str, source: &Rendered) -> String {
+        match &self.redactor {
+            Some(redactor) => redactor.diagnostics(stderr, source),
+            None => stderr.to_string(),
+        }
+    }
+}
+
+impl CompileOutput {
+    /// Compiler messages, located in the payload rather than the rendered source
+    #[rune::function]
+    pub fn diagnostics(&self) -> Vec<CompilerDiagnostic> {
+        self.diagnostics.clone()
+    }
+}
+
+impl RenderedSource {
+    /// Rendered source text
+    #[rune::function]
+    pub fn source(&self) -> String {
+        self.0.source.clone()
+    }
+
+    /// The player's payload, which diagnostic positions refer to
+    #[rune::function]
+    pub fn payload(&self) -> String {
+        self.0.payload().to_string()
+    }
+}
+
+/// Accept C++ source either as a plain string or as a `RenderedSource`. Only the latter
+/// tells the player's bytes apart, so redaction hides all of a plain string.
+pub(crate) fn source_arg(value: &Value) -> Result<Rendered, io::Error> {
+    if let Ok(source) = value.borrow_string_ref() {
+        return Ok(Rendered::plain(&source));
+    }
+    match value.borrow_ref::<RenderedSource>() {
+        Ok(rendered) => Ok(rendered.0.clone()),
+        Err(_) => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Expected source as a string or a RenderedSource",
+        )),
+    }
 }
 
 impl DataBucket {
@@ -46,6 +485,22 @@ impl DataBucket {
 
     #[rune::function]
     pub fn read(&self, file_path: &str) -> Result<String, io::Error> {
+        self.read_file(file_path)
+    }
+
+    #[rune::function]
+    pub fn list(&self, dpath: &str) -> Result<Vec<String>, io::Error> {
+        self.list_dir(dpath)
+    }
+
+    /// Render a bucket template, replacing `${{user_input}}` with the payload
+    #[rune::function]
+    pub fn render(&self, file_path: &str, user_input: &str) -> Result<RenderedSource, io::Error> {
+        let template = self.read_file(file_path)?;
+        Ok(RenderedSource(template::render(&template, user_input)))
+    }
+
+    fn read_file(&self, file_path: &str) -> Result<String, io::Error> {
         let safe_file_path = normalize_path(file_path);
         let abs_path = to_abs_pathbuf(&safe_file_path, Some(&self.path));
 
@@ -72,8 +527,7 @@ impl DataBucket {
         })
     }
 
-    #[rune::function]
-    pub fn list(&self, dpath: &str) -> Result<Vec<String>, io::Error> {
+    fn list_dir(&self, dpath: &str) -> Result<Vec<String>, io::Error> {
         let safe_path = normalize_path(dpath);
         let abs_path = to_abs_pathbuf(&safe_path, Some(&self.path));
 
@@ -109,11 +563,6 @@ fn file_exists<P: AsRef<Path>, Q: AsRef<Path>>(test_path: P, context_path: Q) ->
 fn security_path_within<P: AsRef<Path>, Q: AsRef<Path>>(test_path: P, super_path: Q) -> bool {
     let super_abs = to_abs_pathbuf::<_, &Path>(super_path.as_ref(), None);
     let cur_abs = to_abs_pathbuf(test_path, Some(super_path.as_ref()));
-    println!(
-        "Security check: super_abs: {}, cur_abs: {}",
-        super_abs.to_string_lossy(),
-        cur_abs.to_string_lossy()
-    );
     cur_abs.starts_with(&super_abs)
 }
 
@@ -146,7 +595,7 @@ fn to_abs_pathbuf<P: AsRef<Path>, Q: AsRef<Path>>(
 }
 
 /// dummy normalize path (`/path/to/foo/../bar` to `/path/to/bar`)
-fn normalize_path<P: AsRef<Path>>(path: P) -> PathBuf {
+pub(crate) fn normalize_path<P: AsRef<Path>>(path: P) -> PathBuf {
     let path_buf = path.as_ref();
     let mut components = Vec::new();
 
@@ -0,0 +1,450 @@
+use rune::{Any, ContextError, Module, Value};
+use std::collections::BTreeSet;
+use std::io;
+
+use crate::cpp::{self, TokenKind};
+use crate::engine::modules::context::{source_arg, Toolchain};
+use crate::template::Rendered;
+
+const TOKEN_KINDS: [TokenKind; 10] = [
+    TokenKind::Identifier,
+    TokenKind::Keyword,
+    TokenKind::Number,
+    TokenKind::String,
+    TokenKind::Char,
+    TokenKind::Punctuator,
+    TokenKind::Directive,
+    TokenKind::HeaderName,
+    TokenKind::Comment,
+    TokenKind::Unknown,
+];
+
+/// C++ source inspection for jailbox scripts
+#[rune::module(::jailapi::cpp)]
+pub fn module() -> Result<Module, ContextError> {
+    let mut module = Module::from_meta(self::module_meta)?;
+    module.ty::<Token>()?;
+    module.ty::<TokenFilter>()?;
+    module.ty::<Violation>()?;
+    module.function_meta(tokenize)?;
+    module.function_meta(tokenize_with)?;
+    module.function_meta(TokenFilter::new)?;
+    module.function_meta(TokenFilter::ban_kinds)?;
+    module.function_meta(TokenFilter::ban_punctuators)?;
+    module.function_meta(TokenFilter::ban_identifiers)?;
+    module.function_meta(TokenFilter::ban_directives)?;
+    module.function_meta(TokenFilter::ban_alternative_spellings)?;
+    module.function_meta(TokenFilter::check)?;
+    module.function_meta(TokenFilter::check_with)?;
+    module.function_meta(Violation::token)?;
+    module.ty::<Preprocessed>()?;
+    module.ty::<LineMarker>()?;
+    module.function_meta(preprocess)?;
+    module.function_meta(Preprocessed::markers)?;
+    module.function_meta(Preprocessed::code)?;
+    module.function_meta(LineMarker::flags)?;
+    module.ty::<Ast>()?;
+    module.ty::<AstNode>()?;
+    module.function_meta(ast)?;
+    module.function_meta(Ast::nodes)?;
+    module.function_meta(Ast::find_calls)?;
+    module.function_meta(Ast::find_decls)?;
+    module.function_meta(Ast::contains)?;
+    Ok(module)
+}
+
+/// A preprocessing token, as the compiler will see it and as the player wrote it
+#[derive(Clone, Debug, Any)]
+#[rune(item = ::jailapi::cpp)]
+pub struct Token {
+    /// `identifier`, `keyword`, `number`, `string`, `char`, `punctuator`, `directive`,
+    /// `header_name`, `comment` or `unknown`
+    #[rune(get)]
+    kind: String,
+    /// Canonical form, e.g. `{` for `<%` and `&&` for `and`; the name for directives
+    #[rune(get)]
+    text: String,
+    /// Exactly as written
+    #[rune(get)]
+    spelling: String,
+    /// Byte offset into the source
+    #[rune(get)]
+    offset: i64,
+    #[rune(get)]
+    line: i64,
+    #[rune(get)]
+    column: i64,
+    /// Written through a digraph, trigraph, alternative token, line splice or `\u` escape
+    #[rune(get)]
+    alternative: bool,
+}
+
+impl From<cpp::Token> for Token {
+    fn from(token: cpp::Token) -> Self {
+        Self {
+            kind: token.kind.as_str().to_string(),
+            text: token.text,
+            spelling: token.spelling,
+            offset: token.offset as i64,
+            line: token.line as i64,
+            column: token.column as i64,
+            alternative: token.alternative,
+        }
+    }
+}
+
+/// Split C++ source into tokens, without whitespace. Trigraphs are left alone, as in
+/// GCC's default GNU dialects, but still mark their tokens `alternative`.
+#[rune::function]
+pub fn tokenize(source: &str) -> Result<Vec<Token>, io::Error> {
+    Ok(lex(source, cpp::LexOptions::default())?
+        .into_iter()
+        .map(Token::from)
+        .collect())
+}
+
+/// Like `tokenize`, but reading trigraphs the way the toolchain's compiler does
+#[rune::function]
+pub fn tokenize_with(source: &str, toolchain: &Toolchain) -> Result<Vec<Token>, io::Error> {
+    Ok(lex(source, toolchain.lex_options()?)?
+        .into_iter()
+        .map(Token::from)
+        .collect())
+}
+
+fn lex(source: &str, options: cpp::LexOptions) -> Result<Vec<cpp::Token>, io::Error> {
+    cpp::tokenize(source, options)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+}
+
+/// Preprocessor output of a submission
+#[derive(Debug, Any)]
+#[rune(item = ::jailapi::cpp)]
+pub struct Preprocessed {
+    /// Expanded source, including line markers
+    #[rune(get)]
+    text: String,
+    /// Compiler diagnostics, redacted like `compile`'s
+    #[rune(get)]
+    stderr: String,
+    #[rune(get)]
+    success: bool,
+    #[rune(get)]
+    timed_out: bool,
+}
+
+impl Preprocessed {
+    /// Line markers mapping `text` back to the files it came from
+    #[rune::function]
+    pub fn markers(&self) -> Vec<LineMarker> {
+        cpp::line_markers(&self.text)
+            .into_iter()
+            .map(LineMarker::from)
+            .collect()
+    }
+
+    /// Expanded lines of the submission itself, without headers it included. Lines are
+    /// told apart by include depth, so `#line` can't hide code, but a GNU linemarker can
+    /// fake entering a header: ban `line` directives in payloads that are preprocessed.
+    #[rune::function]
+    pub fn code(&self) -> String {
+        cpp::main_code(&self.text)
+    }
+}
+
+/// `# line "file" flags` in preprocessor output
+#[derive(Clone, Debug, Any)]
+#[rune(item = ::jailapi::cpp)]
+pub struct LineMarker {
+    /// Line of the marker in `Preprocessed::text`
+    #[rune(get)]
+    output_line: i64,
+    /// Line in `file` of the line after the marker
+    #[rune(get)]
+    line: i64,
+    #[rune(get)]
+    file: String,
+    flags: Vec<i64>,
+    /// Renames the file without entering or leaving one, as only `#line` in the source does
+    #[rune(get)]
+    directive: bool,
+}
+
+impl LineMarker {
+    /// 1 entering an include, 2 returning from one, 3 system header, 4 `extern "C"`
+    #[rune::function]
+    pub fn flags(&self) -> Vec<i64> {
+        self.flags.clone()
+    }
+}
+
+impl From<cpp::LineMarker> for LineMarker {
+    fn from(marker: cpp::LineMarker) -> Self {
+        Self {
+            output_line: marker.output_line as i64,
+            line: marker.line as i64,
+            file: marker.file,
+            flags: marker.flags.into_iter().map(i64::from).collect(),
+            directive: marker.directive,
+        }
+    }
+}
+
+/// Run the toolchain's preprocessor over a string or `RenderedSource` in its sandbox
+#[rune::function]
+pub fn preprocess(source: Value, toolchain: &Toolchain) -> Result<Preprocessed, io::Error> {
+    let output = toolchain.preprocess_source(&source_arg(&source)?)?;
+    Ok(Preprocessed {
+        text: output.expanded,
+        stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        success: output.success,
+        timed_out: output.timed_out,
+    })
+}
+
+/// Simplified Clang AST of a submission, without the headers it includes
+#[derive(Debug, Any)]
+#[rune(item = ::jailapi::cpp)]
+pub struct Ast {
+    nodes: Vec<AstNode>,
+    /// Clang found no errors; the AST only covers what it could parse otherwise
+    #[rune(get)]
+    success: bool,
+    /// Clang diagnostics, redacted like `compile`'s
+    #[rune(get)]
+    stderr: String,
+    /// Nodes past the limit were dropped
+    #[rune(get)]
+    truncated: bool,
+}
+
+/// A node of an `Ast`, in pre-order
+#[derive(Clone, Debug, Any)]
+#[rune(item = ::jailapi::cpp)]
+pub struct AstNode {
+    /// Clang's node kind, e.g. `FunctionDecl`, `CallExpr` or `LambdaExpr`
+    #[rune(get)]
+    kind: String,
+    /// Declared or referenced name; what's called for calls, when Clang knows
+    #[rune(get)]
+    name: Option<String>,
+    /// Nesting below the top-level declaration
+    #[rune(get)]
+    depth: i64,
+    /// Position in the payload, `None` for template code
+    #[rune(get)]
+    line: Option<i64>,
+    #[rune(get)]
+    column: Option<i64>,
+    #[rune(get)]
+    offset: Option<i64>,
+}
+
+impl AstNode {
+    fn new(node: cpp::AstNode, source: &Rendered) -> Self {
+        let position = node
+            .line
+            .zip(node.column)
+            .and_then(|(line, column)| source.payload_position(line, column));
+        Self {
+            kind: node.kind,
+            name: node.name,
+            depth: node.depth as i64,
+            line: position.map(|p| p.line as i64),
+            column: position.map(|p| p.column as i64),
+            offset: position.map(|p| p.offset as i64),
+        }
+    }
+}
+
+impl Ast {
+    #[rune::function]
+    pub fn nodes(&self) -> Vec<AstNode> {
+        self.nodes.clone()
+    }
+
+    /// Function, member function, operator and user-defined literal calls. Constructor
+    /// calls are `CXXConstructExpr` nodes instead.
+    #[rune::function]
+    pub fn find_calls(&self) -> Vec<AstNode> {
+        self.nodes
+            .iter()
+            .filter(|node| cpp::ast::is_call(&node.kind))
+            .cloned()
+            .collect()
+    }
+
+    /// Declarations of a kind, given as Clang names it or without the `Decl` suffix,
+    /// e.g. `FunctionDecl` or `Function`
+    #[rune::function]
+    pub fn find_decls(&self, kind: &str) -> Vec<AstNode> {
+        let decl = format!("{}Decl", kind.trim_end_matches("Decl"));
+        self.nodes
+            .iter()
+            .filter(|node| node.kind == decl)
+            .cloned()
+            .collect()
+    }
+
+    /// Whether any node is of `kind`, e.g. `LambdaExpr` or `GotoStmt`
+    #[rune::function]
+    pub fn contains(&self, kind: &str) -> bool {
+        self.nodes.iter().any(|node| node.kind == kind)
+    }
+}
+
+/// Dump the Clang AST of a string or `RenderedSource` in the toolchain's sandbox, with
+/// the profile's standard, defines and include dirs. Fails when Clang isn't installed.
+#[rune::function]
+pub fn ast(source: Value, toolchain: &Toolchain) -> Result<Ast, io::Error> {
+    let source = source_arg(&source)?;
+    let (ast, output) = toolchain.ast_source(&source)?;
+    Ok(Ast {
+        nodes: ast
+            .nodes
+            .into_iter()
+            .map(|node| AstNode::new(node, &source))
+            .collect(),
+        success: output.success,
+        stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        truncated: ast.truncated,
+    })
+}
+
+/// Bans on tokens rather than characters, so every spelling of a token is caught
+#[derive(Debug, Default, Any)]
+#[rune(item = ::jailapi::cpp)]
+pub struct TokenFilter {
+    kinds: BTreeSet<&'static str>,
+    punctuators: BTreeSet<String>,
+    identifiers: BTreeSet<String>,
+    directives: BTreeSet<String>,
+    alternative_spellings: bool,
+}
+
+/// A token a `TokenFilter` rejected
+#[derive(Debug, Any)]
+#[rune(item = ::jailapi::cpp)]
+pub struct Violation {
+    #[rune(get)]
+    reason: String,
+    token: Token,
+}
+
+impl Violation {
+    #[rune::function]
+    pub fn token(&self) -> Token {
+        self.token.clone()
+    }
+}
+
+impl TokenFilter {
+    #[rune::function(path = Self::new)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ban whole token kinds, e.g. `directive` or `string`
+    #[rune::function]
+    pub fn ban_kinds(&mut self, kinds: Vec<String>) -> Result<(), io::Error> {
+        for kind in kinds {
+            let known = TOKEN_KINDS
+                .iter()
+                .find(|known| known.as_str() == kind)
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("Unknown token kind: {}", kind),
+                    )
+                })?;
+            self.kinds.insert(known.as_str());
+        }
+        Ok(())
+    }
+
+    /// Ban punctuators by canonical text, so `{` also bans `<%` and `??<`. Whole tokens are
+    /// matched: banning `>` leaves `>>` and `->` alone.
+    #[rune::function]
+    pub fn ban_punctuators(&mut self, punctuators: Vec<String>) {
+        self.punctuators.extend(punctuators);
+    }
+
+    /// Ban identifiers and keywords, however they are escaped
+    #[rune::function]
+    pub fn ban_identifiers(&mut self, identifiers: Vec<String>) {
+        self.identifiers.extend(identifiers);
+    }
+
+    /// Ban preprocessor directives by name, e.g. `define` or `include`
+    #[rune::function]
+    pub fn ban_directives(&mut self, directives: Vec<String>) {
+        self.directives.extend(directives);
+    }
+
+    /// Ban digraphs, trigraphs, alternative tokens, line splices and `\u` escapes
+    #[rune::function]
+    pub fn ban_alternative_spellings(&mut self) {
+        self.alternative_spellings = true;
+    }
+
+    fn violation(&self, token: &cpp::Token) -> Option<String> {
+        let kind = token.kind.as_str();
+        if self.kinds.contains(kind) {
+            return Some(format!("{} tokens are not allowed", kind));
+        }
+        match token.kind {
+            TokenKind::Punctuator if self.punctuators.contains(&token.text) => {
+                return Some(format!("`{}` is not allowed", token.text));
+            }
+            TokenKind::Identifier | TokenKind::Keyword
+                if self.identifiers.contains(&token.text) =>
+            {
+                return Some(format!("`{}` is not allowed", token.text));
+            }
+            TokenKind::Directive if self.directives.contains(&token.text) => {
+                return Some(format!("#{} is not allowed", token.text));
+            }
+            _ => {}
+        }
+        if self.alternative_spellings && token.alternative {
+            return Some(format!(
+                "`{}` is an alternative spelling of `{}`",
+                token.spelling, token.text
+            ));
+        }
+        None
+    }
+
+    /// Every token of `source` the filter rejects, in order
+    #[rune::function]
+    pub fn check(&self, source: &str) -> Result<Vec<Violation>, io::Error> {
+        self.violations(source, cpp::LexOptions::default())
+    }
+
+    /// Like `check`, but reading trigraphs the way the toolchain's compiler does
+    #[rune::function]
+    pub fn check_with(
+        &self,
+        source: &str,
+        toolchain: &Toolchain,
+    ) -> Result<Vec<Violation>, io::Error> {
+        self.violations(source, toolchain.lex_options()?)
+    }
+
+    fn violations(
+        &self,
+        source: &str,
+        options: cpp::LexOptions,
+    ) -> Result<Vec<Violation>, io::Error> {
+        Ok(lex(source, options)?
+            .into_iter()
+            .filter_map(|token| {
+                let reason = self.violation(&token)?;
+                Some(Violation {
+                    reason,
+                    token: token.into(),
+                })
+            })
+            .collect())
+    }
+}
@@ -1,3 +1,5 @@
+pub mod binary;
 pub mod context;
+pub mod cpp;
 
 pub use context::module;
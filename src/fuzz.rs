@@ -0,0 +1,238 @@
+use regex::Regex;
+use serde::Serialize;
+use std::collections::HashSet;
+
+use crate::batch::BatchInput;
+use crate::engine::{CompiledScript, LocalCalls, UserInput};
+
+/// Upper bound on retained inputs, so echo-style checks don't grow the corpus forever
+const MAX_CORPUS: usize = 4096;
+
+/// Tuning knobs for a fuzzing run
+pub struct FuzzConfig {
+    /// Characters mutations may insert, already stripped of blacklisted ones
+    pub alphabet: Vec<char>,
+    /// Characters the challenge filter rejects; never produced by the fuzzer
+    pub blacklist: HashSet<char>,
+    /// Outputs matching any of these count as a successful bypass
+    pub success_patterns: Vec<Regex>,
+    pub iterations: usize,
+    pub max_len: usize,
+    pub seed: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FindingKind {
+    /// Output matched the flag pattern or a success marker
+    Win,
+    /// The script threw at runtime
+    Crash,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FuzzFinding {
+    pub kind: FindingKind,
+    pub input: String,
+    /// Smallest input found that still reproduces the finding
+    pub minimized: String,
+    pub output: String,
+    pub iteration: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FuzzReport {
+    pub seed: u64,
+    pub iterations: usize,
+    pub corpus_size: usize,
+    pub findings: Vec<FuzzFinding>,
+}
+
+/// xorshift64*, good enough to drive mutations reproducibly from a seed
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Rng(seed.max(1))
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        if n == 0 {
+            0
+        } else {
+            (self.next() % n as u64) as usize
+        }
+    }
+}
+
+/// Mutate seeds through `check` looking for outputs that leak the flag or crash the script
+pub fn fuzz(
+    script: &CompiledScript,
+    calls: &LocalCalls,
+    seeds: Vec<BatchInput>,
+    config: &FuzzConfig,
+    mut on_finding: impl FnMut(&FuzzFinding),
+) -> FuzzReport {
+    let mut rng = Rng::new(config.seed);
+
+    let mut corpus: Vec<Vec<char>> = seeds
+        .iter()
+        .map(|seed| {
+            seed.content
+                .chars()
+                .filter(|c| !config.blacklist.contains(c))
+                .take(config.max_len)
+                .collect()
+        })
+        .collect();
+    if corpus.is_empty() {
+        corpus.push(Vec::new());
+    }
+
+    // Outputs already seen; an input producing a new one is kept for further mutation
+    let mut seen_outputs = HashSet::new();
+    let mut seen_findings = HashSet::new();
+    let mut findings = Vec::new();
+
+    for iteration in 0..config.iterations {
+        let parent = &corpus[rng.below(corpus.len())];
+        let other = &corpus[rng.below(corpus.len())];
+        let candidate = mutate(&mut rng, parent, other, config);
+        let input: String = candidate.iter().collect();
+
+        let (kind, output) = match classify(script, calls, &input, config) {
+            (Some(kind), output) => (kind, output),
+            (None, output) => {
+                if corpus.len() < MAX_CORPUS && seen_outputs.insert(output) {
+                    corpus.push(candidate);
+                }
+                continue;
+            }
+        };
+
+        let minimized = minimize(script, calls, &input, kind, config);
+        if !seen_findings.insert((kind, minimized.clone())) {
+            continue;
+        }
+
+        let finding = FuzzFinding {
+            kind,
+            input,
+            minimized,
+            output,
+            iteration,
+        };
+        on_finding(&finding);
+        findings.push(finding);
+    }
+
+    FuzzReport {
+        seed: config.seed,
+        iterations: config.iterations,
+        corpus_size: corpus.len(),
+        findings,
+    }
+}
+
+fn classify(
+    script: &CompiledScript,
+    calls: &LocalCalls,
+    input: &str,
+    config: &FuzzConfig,
+) -> (Option<FindingKind>, String) {
+    let input = UserInput::Text(input.to_string());
+    match calls.run(None, |call| script.call_check_with(&input, call)) {
+        Ok(Ok(output)) | Ok(Err(output)) => {
+            let win = config.success_patterns.iter().any(|p| p.is_match(&output));
+            (win.then_some(FindingKind::Win), output)
+        }
+        Err(err) => (Some(FindingKind::Crash), err.to_string()),
+    }
+}
+
+fn mutate(rng: &mut Rng, parent: &[char], other: &[char], config: &FuzzConfig) -> Vec<char> {
+    let mut candidate = parent.to_vec();
+
+    // Stack a few mutations so the search can move further per execution
+    for _ in 0..=rng.below(4) {
+        let random_char = |rng: &mut Rng| config.alphabet[rng.below(config.alphabet.len())];
+
+        match rng.below(5) {
+            0 if !config.alphabet.is_empty() => {
+                let at = rng.below(candidate.len() + 1);
+                candidate.insert(at, random_char(rng));
+            }
+            1 if !candidate.is_empty() => {
+                candidate.remove(rng.below(candidate.len()));
+            }
+            2 if !candidate.is_empty() && !config.alphabet.is_empty() => {
+                let at = rng.below(candidate.len());
+                candidate[at] = random_char(rng);
+            }
+            3 if !candidate.is_empty() => {
+                let start = rng.below(candidate.len());
+                let end = start + 1 + rng.below(candidate.len() - start);
+                let chunk = candidate[start..end].to_vec();
+                let at = rng.below(candidate.len() + 1);
+                candidate.splice(at..at, chunk);
+            }
+            _ if !other.is_empty() => {
+                let start = rng.below(other.len());
+                let end = start + 1 + rng.below(other.len() - start);
+                let at = rng.below(candidate.len() + 1);
+                candidate.splice(at..at, other[start..end].iter().copied());
+            }
+            _ => {}
+        }
+    }
+
+    candidate.truncate(config.max_len);
+    candidate
+}
+
+/// Delta-debugging style reduction: drop ever smaller chunks while the finding still reproduces
+fn minimize(
+    script: &CompiledScript,
+    calls: &LocalCalls,
+    input: &str,
+    kind: FindingKind,
+    config: &FuzzConfig,
+) -> String {
+    let mut current: Vec<char> = input.chars().collect();
+    let mut chunk = current.len().div_ceil(2).max(1);
+
+    loop {
+        let mut reduced = false;
+        let mut start = 0;
+
+        while start < current.len() {
+            let end = (start + chunk).min(current.len());
+            let mut candidate = current.clone();
+            candidate.drain(start..end);
+            let text: String = candidate.iter().collect();
+
+            if classify(script, calls, &text, config).0 == Some(kind) {
+                current = candidate;
+                reduced = true;
+            } else {
+                start += chunk;
+            }
+        }
+
+        if chunk == 1 && !reduced {
+            break;
+        }
+        if !reduced {
+            chunk = chunk.div_ceil(2);
+        }
+    }
+
+    current.into_iter().collect()
+}
@@ -0,0 +1,247 @@
+use anyhow::{anyhow, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::{path::Path, sync::Arc, time::Instant};
+use tokio::task::JoinSet;
+
+use crate::engine::{LocalCalls, RuneEngine, UserInput};
+
+/// Verdict a fixture expects from `check`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Verdict {
+    Ok,
+    Err,
+}
+
+/// A single expected-verdict test case, as written in the fixtures file
+#[derive(Debug, Deserialize)]
+pub struct Fixture {
+    pub name: Option<String>,
+    pub input: String,
+    pub expect: Verdict,
+    /// Expected output, compared as JSON
+    pub json: Option<serde_json::Value>,
+    /// Regex the raw output must match
+    pub matches: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FixtureResult {
+    pub name: String,
+    pub passed: bool,
+    pub verdict: Option<Verdict>,
+    pub output: String,
+    /// Why the fixture failed, as a human readable message
+    pub failure: Option<String>,
+    /// Line diff between expected and actual output when a JSON match failed
+    pub diff: Option<String>,
+    pub duration_ms: u128,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TestReport {
+    pub passed: usize,
+    pub failed: usize,
+    pub results: Vec<FixtureResult>,
+}
+
+pub fn load_fixtures(path: &Path) -> Result<Vec<Fixture>> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| anyhow!("Failed to read fixtures {}: {}", path.display(), e))?;
+    serde_json::from_str(&content)
+        .map_err(|e| anyhow!("Invalid fixtures file {}: {}", path.display(), e))
+}
+
+/// Run every fixture through `check` concurrently, each in a sandbox of its own, keeping
+/// the file order in the report
+pub async fn run_fixtures(
+    rune_engine: Arc<RuneEngine>,
+    calls: Arc<LocalCalls>,
+    fixtures: Vec<Fixture>,
+) -> Result<TestReport> {
+    let mut tasks = JoinSet::new();
+
+    for (index, fixture) in fixtures.into_iter().enumerate() {
+        let rune_engine = rune_engine.clone();
+        let calls = calls.clone();
+        tasks.spawn_blocking(move || {
+            let started = Instant::now();
+            let input = UserInput::Text(fixture.input.clone());
+            let outcome = calls.run(None, |call| {
+                tokio::runtime::Handle::current()
+                    .block_on(rune_engine.call_check_with(&input, call))
+            });
+            let name = fixture
+                .name
+                .clone()
+                .unwrap_or_else(|| format!("fixture #{}", index + 1));
+            let mut result = evaluate(name, &fixture, outcome);
+            result.duration_ms = started.elapsed().as_millis();
+            (index, result)
+        });
+    }
+
+    let mut results = Vec::new();
+    while let Some(joined) = tasks.join_next().await {
+        results.push(joined?);
+    }
+    results.sort_by_key(|(index, _)| *index);
+
+    let results: Vec<_> = results.into_iter().map(|(_, result)| result).collect();
+    let passed = results.iter().filter(|r| r.passed).count();
+
+    Ok(TestReport {
+        passed,
+        failed: results.len() - passed,
+        results,
+    })
+}
+
+fn evaluate(
+    name: String,
+    fixture: &Fixture,
+    outcome: Result<Result<String, String>>,
+) -> FixtureResult {
+    let mut result = FixtureResult {
+        name,
+        passed: false,
+        verdict: None,
+        output: String::new(),
+        failure: None,
+        diff: None,
+        duration_ms: 0,
+    };
+
+    let (verdict, output) = match outcome {
+        Ok(Ok(output)) => (Verdict::Ok, output),
+        Ok(Err(output)) => (Verdict::Err, output),
+        Err(err) => {
+            result.failure = Some(format!("Runtime error: {}", err));
+            return result;
+        }
+    };
+    result.verdict = Some(verdict);
+    result.output = output;
+
+    if verdict != fixture.expect {
+        result.failure = Some(format!(
+            "Expected {:?} verdict, got {:?}",
+            fixture.expect, verdict
+        ));
+        return result;
+    }
+
+    if let Some(expected) = &fixture.json {
+        // Err values are plain messages, so compare them as JSON strings
+        let actual = serde_json::from_str::<serde_json::Value>(&result.output)
+            .unwrap_or_else(|_| serde_json::Value::String(result.output.clone()));
+        if &actual != expected {
+            result.failure = Some("Output does not match expected JSON".to_string());
+            result.diff = Some(line_diff(
+                &serde_json::to_string_pretty(expected).unwrap_or_default(),
+                &serde_json::to_string_pretty(&actual).unwrap_or_default(),
+            ));
+            return result;
+        }
+    }
+
+    if let Some(pattern) = &fixture.matches {
+        match Regex::new(pattern) {
+            Ok(regex) if regex.is_match(&result.output) => {}
+            Ok(_) => {
+                result.failure = Some(format!("Output does not match /{}/", pattern));
+                return result;
+            }
+            Err(e) => {
+                result.failure = Some(format!("Invalid regex /{}/: {}", pattern, e));
+                return result;
+            }
+        }
+    }
+
+    result.passed = true;
+    result
+}
+
+/// Minimal LCS line diff, `-` for expected and `+` for actual lines
+fn line_diff(expected: &str, actual: &str) -> String {
+    let a: Vec<&str> = expected.lines().collect();
+    let b: Vec<&str> = actual.lines().collect();
+
+    let mut lcs = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut diff = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            diff.push_str(&format!("  {}\n", a[i]));
+            i += 1;
+            j += 1;
+        } else if i < a.len() && (j == b.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            diff.push_str(&format!("- {}\n", a[i]));
+            i += 1;
+        } else {
+            diff.push_str(&format!("+ {}\n", b[j]));
+            j += 1;
+        }
+    }
+    diff
+}
+
+/// Render the report as JUnit XML for CI systems
+pub fn to_junit(report: &TestReport) -> String {
+    let total_ms: u128 = report.results.iter().map(|r| r.duration_ms).sum();
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str(&format!(
+        "<testsuite name=\"jailbox\" tests=\"{}\" failures=\"{}\" time=\"{:.3}\">\n",
+        report.results.len(),
+        report.failed,
+        total_ms as f64 / 1000.0
+    ));
+
+    for result in &report.results {
+        xml.push_str(&format!(
+            "  <testcase name=\"{}\" time=\"{:.3}\"",
+            xml_escape(&result.name),
+            result.duration_ms as f64 / 1000.0
+        ));
+        match &result.failure {
+            None => xml.push_str("/>\n"),
+            Some(failure) => {
+                xml.push_str(">\n");
+                xml.push_str(&format!(
+                    "    <failure message=\"{}\">{}</failure>\n",
+                    xml_escape(failure),
+                    xml_escape(result.diff.as_deref().unwrap_or(&result.output))
+                ));
+                xml.push_str("  </testcase>\n");
+            }
+        }
+    }
+
+    xml.push_str("</testsuite>\n");
+    xml
+}
+
+fn xml_escape(value: &str) -> String {
+    // Control characters other than whitespace are not allowed in XML 1.0
+    value
+        .chars()
+        .filter(|c| !c.is_control() || matches!(c, '\n' | '\r' | '\t'))
+        .collect::<String>()
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
@@ -0,0 +1,112 @@
+use serde::Serialize;
+use std::{collections::BTreeMap, path::Path, time::Duration};
+
+use crate::config::ChallengeConfig;
+use crate::engine::RuneEngine;
+
+/// How long `<compiler> --version` may take before the compiler counts as broken
+const COMPILER_PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Serialize)]
+pub struct Check {
+    pub ok: bool,
+    pub detail: String,
+}
+
+impl Check {
+    fn pass(detail: impl Into<String>) -> Self {
+        Self {
+            ok: true,
+            detail: detail.into(),
+        }
+    }
+
+    fn fail(detail: impl Into<String>) -> Self {
+        Self {
+            ok: false,
+            detail: detail.into(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Readiness {
+    pub ready: bool,
+    pub checks: BTreeMap<&'static str, Check>,
+}
+
+/// Run every readiness check; the server is ready only if all of them pass
+pub async fn readiness(
+    rune_engine: &RuneEngine,
+    bucket_path: &Path,
+    sandbox_root: &Path,
+) -> Readiness {
+    let mut checks = BTreeMap::new();
+    checks.insert("bucket", check_bucket(bucket_path));
+    checks.insert("script", check_script(rune_engine));
+    checks.insert("compiler", check_compilers(rune_engine.config()).await);
+    checks.insert("sandbox_root", check_sandbox_root(sandbox_root));
+
+    Readiness {
+        ready: checks.values().all(|check| check.ok),
+        checks,
+    }
+}
+
+fn check_bucket(bucket_path: &Path) -> Check {
+    if bucket_path.is_dir() {
+        Check::pass(bucket_path.display().to_string())
+    } else {
+        Check::fail(format!("{} is not a directory", bucket_path.display()))
+    }
+}
+
+fn check_script(rune_engine: &RuneEngine) -> Check {
+    match rune_engine.compile_status() {
+        Ok(_) => Check::pass("compiled"),
+        Err(err) => Check::fail(err.to_string()),
+    }
+}
+
+/// Probe the compiler of every toolchain profile
+async fn check_compilers(config: &ChallengeConfig) -> Check {
+    let mut details = Vec::new();
+    let mut ok = true;
+    for (name, profile) in config.compile.profiles() {
+        let check = check_compiler(config.profile_compiler(&profile)).await;
+        ok &= check.ok;
+        details.push(format!("{}: {}", name, check.detail));
+    }
+    Check {
+        ok,
+        detail: details.join("; "),
+    }
+}
+
+async fn check_compiler(compiler: &str) -> Check {
+    let probe = tokio::process::Command::new(compiler)
+        .arg("--version")
+        .stdin(std::process::Stdio::null())
+        .kill_on_drop(true)
+        .output();
+
+    match tokio::time::timeout(COMPILER_PROBE_TIMEOUT, probe).await {
+        Ok(Ok(output)) if output.status.success() => {
+            let version = String::from_utf8_lossy(&output.stdout);
+            Check::pass(version.lines().next().unwrap_or(compiler).to_string())
+        }
+        Ok(Ok(output)) => Check::fail(format!(
+            "{} --version exited with {}",
+            compiler, output.status
+        )),
+        Ok(Err(err)) => Check::fail(format!("{}: {}", compiler, err)),
+        Err(_) => Check::fail(format!("{} --version timed out", compiler)),
+    }
+}
+
+fn check_sandbox_root(sandbox_root: &Path) -> Check {
+    match tempfile::tempfile_in(sandbox_root) {
+        Ok(_) => Check::pass(sandbox_root.display().to_string()),
+        Err(err) => Check::fail(format!("{}: {}", sandbox_root.display(), err)),
+    }
+}
@@ -0,0 +1,289 @@
+use anyhow::Result;
+use regex::Regex;
+use serde::Serialize;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::config::{ChallengeConfig, CHALLENGE_CONFIG_FILE};
+use crate::engine::diagnostics::Severity;
+use crate::engine::modules::context::normalize_path;
+use crate::engine::RuneEngine;
+use crate::template::USER_INPUT_PLACEHOLDER;
+
+/// Placeholders the template renderer knows how to fill
+const KNOWN_PLACEHOLDERS: [&str; 1] = [USER_INPUT_PLACEHOLDER];
+
+/// A single problem found in a challenge bucket
+#[derive(Debug, Serialize)]
+pub struct Finding {
+    pub severity: Severity,
+    pub rule: &'static str,
+    pub message: String,
+    pub file: Option<String>,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct LintReport {
+    pub errors: usize,
+    pub warnings: usize,
+    pub findings: Vec<Finding>,
+}
+
+impl LintReport {
+    fn push(&mut self, finding: Finding) {
+        match finding.severity {
+            Severity::Error => self.errors += 1,
+            Severity::Warning => self.warnings += 1,
+        }
+        self.findings.push(finding);
+    }
+
+    fn error(&mut self, rule: &'static str, message: String, file: Option<&Path>) {
+        self.push(Finding {
+            severity: Severity::Error,
+            rule,
+            message,
+            file: file.map(|f| f.to_string_lossy().to_string()),
+            line: None,
+            column: None,
+        });
+    }
+
+    /// Whether the bucket passes, optionally treating warnings as failures
+    pub fn passed(&self, deny_warnings: bool) -> bool {
+        self.errors == 0 && (!deny_warnings || self.warnings == 0)
+    }
+}
+
+/// Validate a challenge bucket: the Rune script, the files it references and its templates
+pub async fn lint_bucket(
+    script_path: &Path,
+    bucket_path: &Path,
+    flag_pattern: &Regex,
+) -> Result<LintReport> {
+    let mut report = LintReport::default();
+
+    if !bucket_path.is_dir() {
+        report.error(
+            "bucket-missing",
+            format!("Data bucket does not exist: {}", bucket_path.display()),
+            Some(bucket_path),
+        );
+        return Ok(report);
+    }
+
+    if let Err(e) = ChallengeConfig::load(bucket_path) {
+        report.error(
+            "challenge-config",
+            e.to_string(),
+            Some(&bucket_path.join(CHALLENGE_CONFIG_FILE)),
+        );
+    } else if script_path.is_file() {
+        lint_script(&mut report, script_path, bucket_path).await?;
+    } else {
+        report.error(
+            "script-missing",
+            format!("Rune script file does not exist: {}", script_path.display()),
+            Some(script_path),
+        );
+    }
+
+    for file in bucket_files(bucket_path)? {
+        // Binary files can neither hold placeholders nor be read by the script
+        let Ok(content) = fs::read_to_string(&file) else {
+            continue;
+        };
+        lint_placeholders(&mut report, &file, &content);
+        lint_hardcoded_flag(&mut report, &file, &content, flag_pattern);
+    }
+
+    Ok(report)
+}
+
+async fn lint_script(
+    report: &mut LintReport,
+    script_path: &Path,
+    bucket_path: &Path,
+) -> Result<()> {
+    let rune_engine = RuneEngine::new(script_path, bucket_path).await?;
+    let info = rune_engine.inspect()?;
+
+    for diagnostic in info.diagnostics {
+        report.push(Finding {
+            severity: diagnostic.severity,
+            rule: match diagnostic.severity {
+                Severity::Error => "rune-compile-error",
+                Severity::Warning => "rune-compile-warning",
+            },
+            message: diagnostic.message,
+            file: diagnostic.file,
+            line: diagnostic.line,
+            column: diagnostic.column,
+        });
+    }
+
+    // Entry points and declared actions, with their argument count including `ctx`
+    let config = rune_engine.config();
+    let mut expected = vec![
+        (config.entry_points.collect.as_str(), 1),
+        (config.entry_points.check.as_str(), 2),
+    ];
+    for name in config.actions.keys() {
+        if let Some((function, action)) = config.action(name) {
+            expected.push((function, action.args + 1));
+        }
+    }
+
+    if let Some(functions) = info.functions {
+        for (name, arity) in expected {
+            match functions.get(name) {
+                None => report.error(
+                    "entry-point-missing",
+                    format!("Script does not define `{}`", name),
+                    Some(script_path),
+                ),
+                Some(&found) if found != arity => report.error(
+                    "entry-point-arity",
+                    format!("`{}` takes {} argument(s), expected {}", name, found, arity),
+                    Some(script_path),
+                ),
+                Some(_) => {}
+            }
+        }
+    }
+
+    // Literal paths passed to `bucket().read(..)` / `bucket().list(..)`
+    let source = fs::read_to_string(script_path)?;
+    let reference = Regex::new(r#"\.(read|list)\(\s*"((?:[^"\\]|\\.)*)"\s*\)"#)?;
+    let bucket_root = normalize_path(bucket_path);
+
+    for captures in reference.captures_iter(&source) {
+        let (method, path) = (&captures[1], &captures[2]);
+        let (line, column) = line_column(&source, captures.get(2).unwrap().start());
+        let target = normalize_path(bucket_path.join(path));
+
+        let (rule, message) = if !target.starts_with(&bucket_root) {
+            (
+                "bucket-path-escape",
+                format!("`{}(\"{}\")` points outside the data bucket", method, path),
+            )
+        } else if method == "read" && !target.is_file() {
+            (
+                "bucket-file-missing",
+                format!("`read(\"{}\")` refers to a missing bucket file", path),
+            )
+        } else if method == "list" && !target.is_dir() {
+            (
+                "bucket-file-missing",
+                format!("`list(\"{}\")` refers to a missing bucket directory", path),
+            )
+        } else {
+            continue;
+        };
+
+        report.push(Finding {
+            severity: Severity::Error,
+            rule,
+            message,
+            file: Some(script_path.to_string_lossy().to_string()),
+            line: Some(line),
+            column: Some(column),
+        });
+    }
+
+    Ok(())
+}
+
+/// Check `${{name}}` placeholders are well-formed and known to the renderer
+fn lint_placeholders(report: &mut LintReport, file: &Path, content: &str) {
+    let mut offset = 0;
+
+    while let Some(start) = content[offset..].find("${{").map(|i| offset + i) {
+        let (line, column) = line_column(content, start);
+        let body_start = start + 3;
+
+        let finding = match content[body_start..].find("}}") {
+            None => Some((
+                Severity::Error,
+                "Unterminated `${{` placeholder".to_string(),
+            )),
+            Some(len) => {
+                let name = content[body_start..body_start + len].trim();
+                offset = body_start + len + 2;
+
+                if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                    Some((
+                        Severity::Error,
+                        format!("Malformed placeholder `${{{{{}}}}}`", name),
+                    ))
+                } else if !KNOWN_PLACEHOLDERS.contains(&name) {
+                    Some((
+                        Severity::Warning,
+                        format!(
+                            "Unknown placeholder `${{{{{}}}}}` will not be rendered",
+                            name
+                        ),
+                    ))
+                } else {
+                    None
+                }
+            }
+        };
+
+        if let Some((severity, message)) = finding {
+            report.push(Finding {
+                severity,
+                rule: "template-placeholder",
+                message,
+                file: Some(file.to_string_lossy().to_string()),
+                line: Some(line),
+                column: Some(column),
+            });
+        }
+
+        if offset <= start {
+            break;
+        }
+    }
+}
+
+/// Any bucket file is readable by the script, so a literal flag there is one bug away from leaking
+fn lint_hardcoded_flag(report: &mut LintReport, file: &Path, content: &str, flag_pattern: &Regex) {
+    for found in flag_pattern.find_iter(content) {
+        let (line, column) = line_column(content, found.start());
+        report.push(Finding {
+            severity: Severity::Warning,
+            rule: "hardcoded-flag",
+            message: "Flag-like string in a bucket file readable by the script".to_string(),
+            file: Some(file.to_string_lossy().to_string()),
+            line: Some(line),
+            column: Some(column),
+        });
+    }
+}
+
+fn bucket_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            files.extend(bucket_files(&path)?);
+        } else {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// 1-based line and column of a byte offset
+fn line_column(content: &str, offset: usize) -> (usize, usize) {
+    let before = &content[..offset];
+    let line = before.matches('\n').count() + 1;
+    let column = before.rsplit('\n').next().unwrap_or("").chars().count() + 1;
+    (line, column)
+}
@@ -1,25 +1,61 @@
 use anyhow::Result;
 use axum::{
-    http::StatusCode,
-    response::IntoResponse,
+    extract::{ConnectInfo, DefaultBodyLimit},
+    http::{HeaderValue, StatusCode},
+    middleware,
+    response::{IntoResponse, Response},
     routing::{get, post},
-    Json, Router,
+    Extension, Json, Router,
 };
 use clap::Parser;
 use colored::Colorize;
-use serde_json;
-use std::{path::PathBuf, sync::Arc};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::io::IsTerminal;
+use std::{net::SocketAddr, path::PathBuf, sync::Arc};
 use tower::ServiceBuilder;
-use tower_http::{cors::CorsLayer, services::ServeDir};
+use tower_http::{
+    cors::{AllowHeaders, AllowMethods, AllowOrigin, CorsLayer},
+    services::ServeDir,
+};
 use uuid::Uuid;
 
+mod audit;
+mod auth;
+mod batch;
+mod binary;
 mod cli;
+mod compiler;
+mod config;
+mod cpp;
+mod ctfd;
 mod engine;
+mod fuzz;
+mod harness;
+mod health;
+mod lint;
+mod metrics;
+mod redact;
 mod sandbox;
+mod submission;
+mod template;
 
-use cli::{Args, Commands};
-use engine::RuneEngine;
-use sandbox::SandboxManager;
+use audit::{AuditEntry, AuditLog, Change, ReplayResult, ReplaySummary};
+use auth::{AuthState, Identity, TeamTokens};
+use batch::{BatchInput, BatchResult, BatchSummary, Outcome};
+use cli::{
+    Args, AuthArgs, BatchFormat, Commands, CompileArgs, OutputFormat, SandboxArgs, TestFormat,
+};
+use compiler::Toolchains;
+use ctfd::{CtfdClient, SolveTracker};
+use engine::diagnostics::{self, Diagnostic};
+use engine::{CallContext, CompileError, CompiledScript, LocalCalls, RuneEngine, UnknownAction};
+use fuzz::{FuzzConfig, FuzzFinding};
+use harness::TestReport;
+use lint::LintReport;
+use redact::Redactor;
+use sandbox::{Sandbox, SandboxLimits, SandboxManager, ServerBusy};
+use submission::SubmissionError;
 
 const MAIN_RUNE_FILE: &str = "configure.rn";
 
@@ -70,11 +106,76 @@ fn format_result_output(result: &Result<String, String>, parse_json: bool) {
     }
 }
 
+fn format_error_output(err: &anyhow::Error) {
+    match err.downcast_ref::<CompileError>() {
+        Some(compile_error) => {
+            let stderr = std::io::stderr();
+            eprint!(
+                "{}",
+                diagnostics::render(&compile_error.diagnostics, stderr.is_terminal())
+            );
+            eprintln!("{} {}", "Compile Error:".red(), compile_error);
+        }
+        None => eprintln!("{} {}", "RunTime Error:".red(), err),
+    }
+}
+
+/// Header of successful script responses listing the script's compile warnings, as JSON
+const DIAGNOSTICS_HEADER: &str = "x-script-diagnostics";
+
+/// JSON body returned for failed API requests
+#[derive(Serialize)]
+struct ErrorEnvelope {
+    error: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    diagnostics: Option<Vec<Diagnostic>>,
+    /// Machine readable details of a rejected request (`code`, `limit`, ...)
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    details: Option<serde_json::Value>,
+}
+
+/// Build an error response; script diagnostics are only attached when exposed to admins
+fn error_response(status: StatusCode, err: &anyhow::Error, expose_diagnostics: bool) -> Response {
+    let (status, diagnostics) = match err.downcast_ref::<CompileError>() {
+        Some(compile_error) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            expose_diagnostics.then(|| compile_error.diagnostics.clone()),
+        ),
+        None if err.is::<UnknownAction>() => (StatusCode::NOT_FOUND, None),
+        None if err.is::<ServerBusy>() => (StatusCode::SERVICE_UNAVAILABLE, None),
+        None => (status, None),
+    };
+    let (status, details) = match err.downcast_ref::<SubmissionError>() {
+        Some(rejection) => (rejection.status(), serde_json::to_value(rejection).ok()),
+        None => (status, None),
+    };
+    let envelope = ErrorEnvelope {
+        error: err.to_string(),
+        diagnostics,
+        details,
+    };
+    (status, Json(envelope)).into_response()
+}
+
 #[derive(Clone)]
 struct AppState {
     rune_engine: Arc<RuneEngine>,
     sandbox_manager: Arc<SandboxManager>,
+    toolchains: Arc<Toolchains>,
+    redactor: Arc<Redactor>,
+    solves: Arc<SolveTracker>,
+    audit: Option<Arc<AuditLog>>,
+    /// Challenge name recorded in the audit log
+    challenge: Arc<str>,
     bucket_path: PathBuf,
+    expose_diagnostics: bool,
+}
+
+impl AppState {
+    /// Diagnostics are shown to admins even when not exposed globally
+    fn expose_diagnostics(&self, identity: &Identity) -> bool {
+        self.expose_diagnostics || identity.admin
+    }
 }
 
 #[tokio::main]
@@ -87,22 +188,127 @@ async fn main() -> Result<()> {
             host,
             dir,
             exec,
-        } => run_server(port, host, dir, exec).await,
+            expose_diagnostics,
+            audit_log,
+            metrics_addr,
+            shutdown_timeout,
+            sandbox,
+            compile,
+            auth,
+        } => {
+            run_server(
+                port,
+                host,
+                dir,
+                exec,
+                expose_diagnostics,
+                audit_log,
+                metrics_addr,
+                shutdown_timeout,
+                sandbox,
+                compile,
+                auth,
+            )
+            .await
+        }
         Commands::Collect { exec, dir, parse } => run_collect(exec, dir, parse).await,
         Commands::Check {
             exec,
             input,
+            input_file,
+            stdin,
+            input_dir,
+            dir,
+            parse,
+            format,
+            json_input,
+        } => {
+            let inputs = batch::gather_inputs(input, &input_file, stdin, input_dir.as_deref())?;
+            run_check(exec, inputs, dir, parse, format, json_input).await
+        }
+        Commands::Lint {
+            exec,
+            dir,
+            format,
+            deny_warnings,
+            flag_pattern,
+        } => run_lint(exec, dir, format, deny_warnings, flag_pattern).await,
+        Commands::Test {
+            exec,
+            dir,
+            fixtures,
+            format,
+        } => run_test(exec, dir, fixtures, format).await,
+        Commands::Call {
+            name,
+            exec,
+            arg,
             dir,
             parse,
-        } => run_check(exec, input, dir, parse).await,
+        } => run_call(name, exec, arg, dir, parse).await,
+        Commands::Fuzz {
+            exec,
+            dir,
+            corpus,
+            alphabet,
+            blacklist,
+            flag_pattern,
+            marker,
+            iterations,
+            max_len,
+            seed,
+            format,
+        } => {
+            let blacklist: HashSet<char> = blacklist.chars().collect();
+            let alphabet = alphabet.unwrap_or_else(|| (' '..='~').chain(['\n']).collect());
+            let mut success_patterns = vec![regex::Regex::new(&flag_pattern)?];
+            for pattern in &marker {
+                success_patterns.push(regex::Regex::new(pattern)?);
+            }
+            let seed = seed.unwrap_or_else(|| {
+                std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map(|d| d.as_nanos() as u64)
+                    .unwrap_or(1)
+            });
+
+            let config = FuzzConfig {
+                alphabet: alphabet
+                    .chars()
+                    .filter(|c| !blacklist.contains(c))
+                    .collect(),
+                blacklist,
+                success_patterns,
+                iterations,
+                max_len,
+                seed,
+            };
+            run_fuzz(exec, dir, corpus, config, format).await
+        }
+        Commands::Replay {
+            log,
+            exec,
+            dir,
+            team,
+            all,
+            format,
+        } => run_replay(log, exec, dir, team, all, format).await,
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn run_server(
     port: u16,
     host: String,
     bucket_path: PathBuf,
     exec: Option<PathBuf>,
+    expose_diagnostics: bool,
+    audit_log: Option<PathBuf>,
+    metrics_addr: Option<String>,
+    shutdown_timeout: u64,
+    sandbox_args: SandboxArgs,
+    compile_args: CompileArgs,
+    auth_args: AuthArgs,
 ) -> Result<()> {
     // Determine Rune script path
     let rune_script_path = match exec {
@@ -132,31 +338,262 @@ async fn run_server(
     println!("  Data bucket: {}", bucket_path.display());
     println!("  Rune script: {}", rune_script_path.display());
 
+    let ctfd = match &auth_args.ctfd_url {
+        Some(url) => {
+            println!("  CTFd: {}", url);
+            Some(Arc::new(CtfdClient::new(url, auth_args.ctfd_secret)?))
+        }
+        None => None,
+    };
+    let team_tokens = match (&auth_args.tokens_file, &ctfd) {
+        (Some(path), _) => Some(TeamTokens::from_file(path)?),
+        (None, Some(client)) => Some(TeamTokens::Ctfd(client.clone())),
+        (None, None) => None,
+    };
+    if team_tokens.is_some() {
+        println!("  Player auth: required");
+    }
+    let auth = Arc::new(AuthState {
+        team_tokens,
+        session_secret: auth_args.session_secret.map(String::into_bytes),
+        session_ttl: auth_args.session_ttl,
+        admin_token: auth_args.admin_token,
+    });
+
     // Initialize components
     let rune_engine = Arc::new(RuneEngine::new(&rune_script_path, &bucket_path).await?);
-    let sandbox_manager = Arc::new(SandboxManager::new());
+    let sandbox_root = sandbox_args
+        .sandbox_root
+        .unwrap_or_else(SandboxManager::default_root);
+    let sandbox_limits = SandboxLimits {
+        quota: sandbox_args.sandbox_quota,
+        disk_cap: sandbox_args.sandbox_disk_cap,
+    };
+    let mut sandbox_manager = SandboxManager::new(sandbox_root, sandbox_limits)?;
+    if let Some(template) = &rune_engine.config().sandbox.template {
+        sandbox_manager = sandbox_manager.with_template(&bucket_path, template)?;
+    }
+    let sandbox_manager = Arc::new(sandbox_manager);
+    println!("  Sandbox root: {}", sandbox_manager.root().display());
+    let swept = sandbox_manager.sweep_stale()?;
+    if !swept.is_empty() {
+        println!(
+            "  Swept {} stale sandbox(es) from previous runs, killed {} process group(s), freed {} bytes",
+            swept.sandboxes, swept.process_groups, swept.bytes
+        );
+    }
+    sandbox_manager
+        .clone()
+        .spawn_janitor(std::time::Duration::from_secs(sandbox_args.sandbox_ttl));
+    let cache_dir = compile_args
+        .compile_cache
+        .unwrap_or_else(Toolchains::default_cache_dir);
+    let toolchains = Arc::new(Toolchains::new(
+        rune_engine.config(),
+        &bucket_path,
+        &cache_dir,
+        compile_args.compile_cache_size,
+    )?);
+    if compile_args.lazy_toolchains {
+        for (name, program) in toolchains.iter() {
+            println!("  Toolchain {}: {}, set up on first use", name, program);
+        }
+    } else {
+        // Set up in a sandbox of its own, outside any submission's quota
+        let setup = Sandbox::new(sandbox_manager.root())?;
+        let compilers = toolchains.setup_all(&setup.handle(SandboxLimits::default()));
+        setup.destroy()?;
+        for compiler in compilers? {
+            println!("  Toolchain {}: {}", compiler.name(), compiler.program());
+            if let Some(pch) = compiler.pch() {
+                println!("    Precompiled headers: {}", pch.display());
+            }
+        }
+    }
+    match toolchains.clang() {
+        Ok(clang) => println!("  AST dumps: {} ({})", clang.program, clang.version),
+        Err(err) => println!("  {}", err),
+    }
+    let redactor = Arc::new(Redactor::new(&rune_engine.config().redact, &bucket_path)?);
+    let solves = Arc::new(SolveTracker::new(&rune_engine.config().solve, ctfd)?);
+    let audit = match &audit_log {
+        Some(path) => {
+            println!("  Audit log: {}", path.display());
+            Some(Arc::new(AuditLog::open(path).await?))
+        }
+        None => None,
+    };
+    let challenge = challenge_name(&rune_engine, &bucket_path).into();
 
     let state = AppState {
         rune_engine,
-        sandbox_manager,
+        sandbox_manager: sandbox_manager.clone(),
+        toolchains,
+        redactor,
+        solves,
+        audit,
+        challenge,
         bucket_path: bucket_path.clone(),
+        expose_diagnostics,
     };
 
+    let mut admin = Router::new().route("/api/admin/script", get(handle_admin_script));
+
+    // Metrics go on their own listener when given one, otherwise behind the admin token
+    match &metrics_addr {
+        Some(address) => {
+            let listener = tokio::net::TcpListener::bind(address).await?;
+            println!("  Metrics: http://{}/metrics", address);
+            let metrics_app = Router::new().route("/metrics", get(metrics::handle_metrics));
+            tokio::spawn(async move {
+                if let Err(err) = axum::serve(listener, metrics_app).await {
+                    eprintln!("Metrics server failed: {}", err);
+                }
+            });
+        }
+        None => admin = admin.route("/metrics", get(metrics::handle_metrics)),
+    }
+    let admin = admin.route_layer(middleware::from_fn(auth::require_admin));
+
     // Create routes
-    let app = Router::new()
+    let api = Router::new()
         .route("/api/collect", get(handle_collect))
-        .route("/api/submit", post(handle_submit))
-        .fallback_service(ServeDir::new("static").append_index_html_on_directories(true))
-        .layer(ServiceBuilder::new().layer(CorsLayer::permissive()))
+        // Body limits come from the challenge config and are enforced by the handlers
+        .route(
+            "/api/submit",
+            post(handle_submit).layer(DefaultBodyLimit::disable()),
+        )
+        .route(
+            "/api/action/{name}",
+            post(handle_action).layer(DefaultBodyLimit::disable()),
+        )
+        .route(
+            "/api/session",
+            post(auth::handle_login)
+                .with_state(auth.clone())
+                .delete(auth::handle_logout),
+        )
+        .merge(admin)
+        .route_layer(middleware::from_fn_with_state(auth, auth::authenticate))
+        .route_layer(middleware::from_fn(metrics::track_requests))
+        // Probes stay reachable without credentials and out of the request metrics
+        .route("/healthz", get(handle_healthz))
+        .route("/readyz", get(handle_readyz))
         .with_state(state);
 
+    let mut app =
+        api.fallback_service(ServeDir::new("static").append_index_html_on_directories(true));
+    if let Some(cors) = cors_layer(&auth_args.cors_origin)? {
+        app = app.layer(ServiceBuilder::new().layer(cors));
+    }
+
     let bind_address = format!("{}:{}", host, port);
     let listener = tokio::net::TcpListener::bind(&bind_address).await?;
     println!("Server running at http://{}", bind_address);
 
-    axum::serve(listener, app).await?;
+    // Stop accepting connections on the first signal, then let running checks finish
+    let stop = Arc::new(tokio::sync::Notify::new());
+    let serve = axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown({
+        let stop = stop.clone();
+        async move { stop.notified().await }
+    });
+    let mut server = tokio::spawn(async move { serve.await });
 
-    Ok(())
+    tokio::select! {
+        result = &mut server => return Ok(result??),
+        _ = shutdown_signal() => {}
+    }
+
+    println!(
+        "Shutting down, waiting up to {}s for {} running check(s)",
+        shutdown_timeout,
+        metrics::ACTIVE_EXECUTIONS.get()
+    );
+    stop.notify_one();
+    let drained =
+        tokio::time::timeout(std::time::Duration::from_secs(shutdown_timeout), server).await;
+
+    let removed = sandbox_manager.cleanup_all().await;
+    if removed > 0 {
+        println!("Removed {} sandbox(es) left behind", removed);
+    }
+
+    match drained {
+        Ok(result) => {
+            result??;
+            Ok(())
+        }
+        Err(_) => {
+            // Scripts still running block their worker threads, so don't wait for them
+            eprintln!(
+                "Shutdown deadline passed with {} check(s) still running",
+                metrics::ACTIVE_EXECUTIONS.get()
+            );
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Resolve on SIGINT or SIGTERM
+async fn shutdown_signal() {
+    let interrupt = tokio::signal::ctrl_c();
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(_) => std::future::pending().await,
+        }
+    };
+
+    tokio::select! {
+        _ = interrupt => {}
+        _ = terminate => {}
+    }
+}
+
+fn challenge_name(rune_engine: &RuneEngine, bucket_path: &std::path::Path) -> String {
+    rune_engine.config().name.clone().unwrap_or_else(|| {
+        bucket_path
+            .canonicalize()
+            .ok()
+            .and_then(|path| {
+                path.file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+            })
+            .unwrap_or_else(|| "challenge".to_string())
+    })
+}
+
+/// CORS for the configured origins, `*` allowing any; `None` when no origin is configured.
+/// Listed origins may send the session cookie, while `*` only allows token requests.
+fn cors_layer(origins: &[String]) -> Result<Option<CorsLayer>> {
+    if origins.is_empty() {
+        return Ok(None);
+    }
+    if origins.iter().any(|origin| origin == "*") {
+        return Ok(Some(CorsLayer::permissive()));
+    }
+
+    let origins = origins
+        .iter()
+        .map(|origin| {
+            HeaderValue::from_str(origin)
+                .map_err(|e| anyhow::anyhow!("Invalid CORS origin {}: {}", origin, e))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(Some(
+        CorsLayer::new()
+            .allow_origin(AllowOrigin::list(origins))
+            // Credentials rule out wildcards, so echo what the browser asks for instead
+            .allow_methods(AllowMethods::mirror_request())
+            .allow_headers(AllowHeaders::mirror_request())
+            .allow_credentials(true),
+    ))
 }
 
 async fn run_collect(exec: Option<PathBuf>, bucket_path: PathBuf, parse_json: bool) -> Result<()> {
@@ -184,17 +621,136 @@ async fn run_collect(exec: Option<PathBuf>, bucket_path: PathBuf, parse_json: bo
         Ok(result) => {
             format_result_output(&result, parse_json);
         }
+        Err(err) => format_error_output(&err),
+    }
+
+    Ok(())
+}
+
+async fn run_check(
+    exec: Option<PathBuf>,
+    inputs: Vec<BatchInput>,
+    bucket_path: PathBuf,
+    parse_json: bool,
+    format: BatchFormat,
+    json_input: bool,
+) -> Result<()> {
+    // Determine Rune script path
+    let file = match exec {
+        Some(path) => path,
+        None => bucket_path.join(MAIN_RUNE_FILE),
+    };
+
+    if !file.exists() {
+        eprintln!("Error: Rune script file does not exist: {}", file.display());
+        std::process::exit(1);
+    }
+
+    if !bucket_path.exists() {
+        eprintln!(
+            "Error: Data bucket does not exist: {}",
+            bucket_path.display()
+        );
+        std::process::exit(1);
+    }
+
+    let rune_engine = RuneEngine::new(&file, &bucket_path).await?;
+    let calls = LocalCalls::new(rune_engine.config(), &bucket_path)?;
+
+    // A single inline payload keeps the plain, unframed output
+    if let ([input], BatchFormat::Human) = (inputs.as_slice(), format) {
+        let result = input.user_input(json_input).and_then(|user_input| {
+            let script = rune_engine.compile()?;
+            calls.run(None, |call| script.call_check_with(&user_input, call))
+        });
+        match result {
+            Ok(result) => {
+                format_result_output(&result, parse_json);
+            }
+            Err(err) => format_error_output(&err),
+        }
+        return Ok(());
+    }
+
+    // Compile once and reuse the unit for every input
+    let script = match rune_engine.compile() {
+        Ok(script) => script,
         Err(err) => {
-            eprintln!("{} {}", "RunTime Error:".red(), err);
+            format_error_output(&err);
+            return Ok(());
+        }
+    };
+
+    let mut summary = BatchSummary::default();
+    let mut results = Vec::new();
+
+    for input in &inputs {
+        let result = batch::run_input(&script, &calls, input, json_input);
+        summary.add(&result);
+
+        match format {
+            BatchFormat::Human => format_batch_result(&result, parse_json),
+            BatchFormat::Jsonl => println!("{}", serde_json::to_string(&result)?),
+            BatchFormat::Json => results.push(result),
+        }
+    }
+
+    match format {
+        BatchFormat::Human => println!(
+            "{} {} input(s): {} Ok, {} Err, {} runtime error(s)",
+            "Summary:".cyan(),
+            summary.total,
+            summary.ok,
+            summary.err,
+            summary.error
+        ),
+        BatchFormat::Jsonl => {
+            let mut line = serde_json::json!({ "summary": summary });
+            if !script.diagnostics().is_empty() {
+                line["diagnostics"] = serde_json::to_value(script.diagnostics())?;
+            }
+            println!("{}", serde_json::to_string(&line)?)
+        }
+        BatchFormat::Json => {
+            let mut report = serde_json::json!({
+                "results": results,
+                "summary": summary,
+            });
+            if !script.diagnostics().is_empty() {
+                report["diagnostics"] = serde_json::to_value(script.diagnostics())?;
+            }
+            println!("{}", serde_json::to_string_pretty(&report)?)
         }
     }
 
     Ok(())
 }
 
-async fn run_check(
+fn format_batch_result(result: &BatchResult, parse_json: bool) {
+    let lossy = if result.lossy {
+        " (not valid UTF-8, decoded lossily)".yellow().to_string()
+    } else {
+        String::new()
+    };
+    println!(
+        "{} {} ({} ms){}",
+        "==>".cyan(),
+        result.name,
+        result.duration_ms,
+        lossy
+    );
+
+    match result.outcome {
+        Outcome::Ok => format_result_output(&Ok(result.output.clone()), parse_json),
+        Outcome::Err => format_result_output(&Err(result.output.clone()), parse_json),
+        Outcome::Error => eprintln!("{} {}", "RunTime Error:".red(), result.output),
+    }
+}
+
+async fn run_call(
+    name: String,
     exec: Option<PathBuf>,
-    user_input: String,
+    args: Vec<String>,
     bucket_path: PathBuf,
     parse_json: bool,
 ) -> Result<()> {
@@ -217,83 +773,607 @@ async fn run_check(
         std::process::exit(1);
     }
 
+    // Arguments are JSON when they parse as such (`--arg 2`), plain strings otherwise
+    let args = args
+        .into_iter()
+        .map(|arg| serde_json::from_str(&arg).unwrap_or(serde_json::Value::String(arg)))
+        .collect();
+
     let rune_engine = RuneEngine::new(&file, &bucket_path).await?;
-    match rune_engine.call_check(&user_input).await {
+    let calls = LocalCalls::new(rune_engine.config(), &bucket_path)?;
+    let result = rune_engine
+        .compile()
+        .and_then(|script| calls.run(None, |call| script.call_action(&name, args, call)));
+    match result {
         Ok(result) => {
             format_result_output(&result, parse_json);
         }
+        Err(err) => format_error_output(&err),
+    }
+
+    Ok(())
+}
+
+async fn run_lint(
+    exec: Option<PathBuf>,
+    bucket_path: PathBuf,
+    format: OutputFormat,
+    deny_warnings: bool,
+    flag_pattern: String,
+) -> Result<()> {
+    // Determine Rune script path
+    let file = match exec {
+        Some(path) => path,
+        None => bucket_path.join(MAIN_RUNE_FILE),
+    };
+    let flag_pattern = regex::Regex::new(&flag_pattern)?;
+
+    let report = lint::lint_bucket(&file, &bucket_path, &flag_pattern).await?;
+    let passed = report.passed(deny_warnings);
+
+    match format {
+        OutputFormat::Human => format_lint_output(&report, passed),
+        OutputFormat::Json => {
+            let mut value = serde_json::to_value(&report)?;
+            value["passed"] = passed.into();
+            println!("{}", serde_json::to_string_pretty(&value)?);
+        }
+    }
+
+    if !passed {
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
+
+fn format_lint_output(report: &LintReport, passed: bool) {
+    for finding in &report.findings {
+        let label = match finding.severity {
+            diagnostics::Severity::Error => "error".red().bold(),
+            diagnostics::Severity::Warning => "warning".yellow().bold(),
+        };
+        println!("{}[{}]: {}", label, finding.rule, finding.message);
+        if let Some(file) = &finding.file {
+            match (finding.line, finding.column) {
+                (Some(line), Some(column)) => {
+                    println!("  {} {}:{}:{}", "-->".blue().bold(), file, line, column)
+                }
+                _ => println!("  {} {}", "-->".blue().bold(), file),
+            }
+        }
+    }
+
+    let summary = format!("{} error(s), {} warning(s)", report.errors, report.warnings);
+    if passed {
+        println!("{} {}", "Lint passed:".green(), summary);
+    } else {
+        println!("{} {}", "Lint failed:".red(), summary);
+    }
+}
+
+async fn run_test(
+    exec: Option<PathBuf>,
+    bucket_path: PathBuf,
+    fixtures: PathBuf,
+    format: TestFormat,
+) -> Result<()> {
+    // Determine Rune script path
+    let file = match exec {
+        Some(path) => path,
+        None => bucket_path.join(MAIN_RUNE_FILE),
+    };
+
+    if !file.exists() {
+        eprintln!("Error: Rune script file does not exist: {}", file.display());
+        std::process::exit(1);
+    }
+
+    if !bucket_path.exists() {
+        eprintln!(
+            "Error: Data bucket does not exist: {}",
+            bucket_path.display()
+        );
+        std::process::exit(1);
+    }
+
+    let fixtures = harness::load_fixtures(&bucket_path.join(fixtures))?;
+    let rune_engine = Arc::new(RuneEngine::new(&file, &bucket_path).await?);
+    let calls = Arc::new(LocalCalls::new(rune_engine.config(), &bucket_path)?);
+
+    let report = harness::run_fixtures(rune_engine, calls, fixtures).await?;
+
+    match format {
+        TestFormat::Human => format_test_output(&report),
+        TestFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+        TestFormat::Junit => print!("{}", harness::to_junit(&report)),
+    }
+
+    if report.failed > 0 {
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
+
+fn format_test_output(report: &TestReport) {
+    for result in &report.results {
+        if result.passed {
+            println!(
+                "{} {} ({} ms)",
+                "PASS".green(),
+                result.name,
+                result.duration_ms
+            );
+            continue;
+        }
+
+        println!(
+            "{} {} ({} ms)",
+            "FAIL".red(),
+            result.name,
+            result.duration_ms
+        );
+        if let Some(failure) = &result.failure {
+            println!("  {}", failure);
+        }
+        match &result.diff {
+            Some(diff) => {
+                for line in diff.lines() {
+                    match line.chars().next() {
+                        Some('-') => println!("  {}", line.red()),
+                        Some('+') => println!("  {}", line.green()),
+                        _ => println!("  {}", line),
+                    }
+                }
+            }
+            None if !result.output.is_empty() => println!("  output: {}", result.output),
+            None => {}
+        }
+    }
+
+    let summary = format!("{} passed, {} failed", report.passed, report.failed);
+    if report.failed == 0 {
+        println!("{} {}", "Test passed:".green(), summary);
+    } else {
+        println!("{} {}", "Test failed:".red(), summary);
+    }
+}
+
+async fn run_fuzz(
+    exec: Option<PathBuf>,
+    bucket_path: PathBuf,
+    corpus: Option<PathBuf>,
+    config: FuzzConfig,
+    format: BatchFormat,
+) -> Result<()> {
+    // Determine Rune script path
+    let file = match exec {
+        Some(path) => path,
+        None => bucket_path.join(MAIN_RUNE_FILE),
+    };
+
+    if !file.exists() {
+        eprintln!("Error: Rune script file does not exist: {}", file.display());
+        std::process::exit(1);
+    }
+
+    if !bucket_path.exists() {
+        eprintln!(
+            "Error: Data bucket does not exist: {}",
+            bucket_path.display()
+        );
+        std::process::exit(1);
+    }
+
+    let seeds = batch::gather_inputs(None, &[], false, corpus.as_deref())?;
+    let rune_engine = RuneEngine::new(&file, &bucket_path).await?;
+    let calls = LocalCalls::new(rune_engine.config(), &bucket_path)?;
+    let script = match rune_engine.compile() {
+        Ok(script) => script,
         Err(err) => {
-            eprintln!("{} {}", "RunTime Error:".red(), err);
+            format_error_output(&err);
+            return Ok(());
+        }
+    };
+
+    if let BatchFormat::Human = format {
+        println!(
+            "{} {} iteration(s), {} seed(s), PRNG seed {}",
+            "Fuzzing:".cyan(),
+            config.iterations,
+            seeds.len(),
+            config.seed
+        );
+    }
+
+    let report = fuzz::fuzz(&script, &calls, seeds, &config, |finding| match format {
+        BatchFormat::Human => format_fuzz_finding(finding),
+        BatchFormat::Jsonl => {
+            println!("{}", serde_json::to_string(finding).unwrap_or_default())
         }
+        BatchFormat::Json => {}
+    });
+
+    match format {
+        BatchFormat::Human => println!(
+            "{} {} finding(s), corpus grew to {} input(s)",
+            "Summary:".cyan(),
+            report.findings.len(),
+            report.corpus_size
+        ),
+        BatchFormat::Jsonl => {}
+        BatchFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+    }
+
+    if !report.findings.is_empty() {
+        std::process::exit(1);
     }
 
     Ok(())
 }
 
+async fn run_replay(
+    log: PathBuf,
+    exec: Option<PathBuf>,
+    bucket_path: PathBuf,
+    team: Option<String>,
+    all: bool,
+    format: BatchFormat,
+) -> Result<()> {
+    // Determine Rune script path
+    let file = match exec {
+        Some(path) => path,
+        None => bucket_path.join(MAIN_RUNE_FILE),
+    };
+
+    if !file.exists() {
+        eprintln!("Error: Rune script file does not exist: {}", file.display());
+        std::process::exit(1);
+    }
+
+    if !bucket_path.exists() {
+        eprintln!(
+            "Error: Data bucket does not exist: {}",
+            bucket_path.display()
+        );
+        std::process::exit(1);
+    }
+
+    let entries = audit::read_log(&log)?
+        .into_iter()
+        .filter(|entry| team.is_none() || entry.team == team)
+        .collect::<Vec<_>>();
+    let rune_engine = RuneEngine::new(&file, &bucket_path).await?;
+    let solves = SolveTracker::new(&rune_engine.config().solve, None)?;
+    let calls = LocalCalls::new(rune_engine.config(), &bucket_path)?;
+    let script = match rune_engine.compile() {
+        Ok(script) => script,
+        Err(err) => {
+            format_error_output(&err);
+            return Ok(());
+        }
+    };
+
+    let mut summary = ReplaySummary::default();
+    let mut results = Vec::new();
+
+    for entry in &entries {
+        let result = audit::replay(&script, &calls, entry, &solves);
+        summary.add(&result);
+        if result.change == Change::Unchanged && !all {
+            continue;
+        }
+
+        match format {
+            BatchFormat::Human => format_replay_result(&result),
+            BatchFormat::Jsonl => println!("{}", serde_json::to_string(&result)?),
+            BatchFormat::Json => results.push(result),
+        }
+    }
+
+    match format {
+        BatchFormat::Human => println!(
+            "{} {} submission(s): {} unchanged, {} changed, {} regression(s), {} new solve(s)",
+            "Summary:".cyan(),
+            summary.total,
+            summary.unchanged,
+            summary.changed,
+            summary.regressions,
+            summary.new_solves
+        ),
+        BatchFormat::Jsonl => println!(
+            "{}",
+            serde_json::to_string(&serde_json::json!({ "summary": summary }))?
+        ),
+        BatchFormat::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(&serde_json::json!({
+                "results": results,
+                "summary": summary,
+            }))?
+        ),
+    }
+
+    if summary.changed > 0 {
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
+
+fn format_replay_result(result: &ReplayResult) {
+    let label = match result.change {
+        Change::Unchanged => "SAME".green(),
+        Change::Output => "OUTPUT".yellow(),
+        Change::Regression => "REGRESSION".red().bold(),
+        Change::Fixed => "FIXED".cyan(),
+        Change::NewSolve => "NEW SOLVE".red().bold(),
+        Change::LostSolve => "LOST SOLVE".yellow().bold(),
+    };
+    println!(
+        "{} {} team {} at {} ({} ms)",
+        label,
+        result.sandbox_id,
+        result.team.as_deref().unwrap_or("-"),
+        result.timestamp,
+        result.duration_ms
+    );
+    if result.change != Change::Unchanged {
+        println!("  logged: {:?} {}", result.logged, result.logged_output);
+        println!("  now:    {:?} {}", result.verdict, result.output);
+    }
+}
+
+fn format_fuzz_finding(finding: &FuzzFinding) {
+    let label = match finding.kind {
+        fuzz::FindingKind::Win => "WIN".red().bold(),
+        fuzz::FindingKind::Crash => "CRASH".yellow().bold(),
+    };
+    println!("{} at iteration {}", label, finding.iteration);
+    println!("  input:     {:?}", finding.input);
+    println!("  minimized: {:?}", finding.minimized);
+    println!("  output:    {}", finding.output);
+}
+
 async fn handle_collect(
     axum::extract::State(state): axum::extract::State<AppState>,
+    Extension(identity): Extension<Identity>,
 ) -> impl IntoResponse {
-    match state.rune_engine.call_collect().await {
-        Ok(result) => {
-            // result is now a String, needs to be parsed as JSON or returned directly
-            match result {
-                Ok(json_str) => (
-                    StatusCode::OK,
-                    [(axum::http::header::CONTENT_TYPE, "application/json")],
-                    json_str,
-                )
-                    .into_response(),
-                Err(error_msg) => (
-                    StatusCode::OK,
-                    [(axum::http::header::CONTENT_TYPE, "text/plain")],
-                    error_msg,
-                )
-                    .into_response(),
-            }
-        }
-        Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+    let expose_diagnostics = state.expose_diagnostics(&identity);
+    let _active = metrics::ActiveExecution::start();
+    let rune_engine = state.rune_engine.clone();
+    let (result, warnings) =
+        run_blocking(move || call_script(&rune_engine, |script| script.call_collect())).await;
+    match result {
+        Ok(result) => script_response(result, &warnings, expose_diagnostics),
+        Err(err) => error_response(StatusCode::INTERNAL_SERVER_ERROR, &err, expose_diagnostics),
     }
 }
 
 async fn handle_submit(
     axum::extract::State(state): axum::extract::State<AppState>,
-    body: String,
+    Extension(identity): Extension<Identity>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    request: axum::extract::Request,
 ) -> impl IntoResponse {
+    let expose_diagnostics = state.expose_diagnostics(&identity);
+
     // Create sandbox environment
     let sandbox_id = Uuid::new_v4().to_string();
-    // let sandbox = match state.sandbox_manager.create_sandbox(&sandbox_id).await {
-    //     Ok(sandbox) => sandbox,
-    //     Err(err) => {
-    //         return (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response();
-    //     }
-    // };
+    let sandbox = match state.sandbox_manager.create_sandbox(&sandbox_id).await {
+        Ok(sandbox) => sandbox,
+        Err(err) => {
+            return error_response(StatusCode::INTERNAL_SERVER_ERROR, &err, expose_diagnostics);
+        }
+    };
 
     // Execute rune script in sandbox
-    let deep_result = state.rune_engine.call_check(&body).await;
+    let submission_config = &state.rune_engine.config().submission;
+    let (user_input, deep_result, warnings, duration_ms) =
+        match submission::read_submission(request, &sandbox, submission_config).await {
+            Ok(user_input) => {
+                let call = CallContext {
+                    sandbox: Some(sandbox.clone()),
+                    toolchains: Some(state.toolchains.clone()),
+                    redactor: Some(state.redactor.clone()),
+                    team: identity.team().map(str::to_string),
+                };
+                let _active = metrics::ActiveExecution::start();
+                let rune_engine = state.rune_engine.clone();
+                // Only the script call is timed, not how long the client took to upload
+                let started = std::time::Instant::now();
+                let (user_input, result, warnings) = run_blocking(move || {
+                    let (result, warnings) = call_script(&rune_engine, |script| {
+                        script.call_check_with(&user_input, &call)
+                    });
+                    (user_input, result, warnings)
+                })
+                .await;
+                let outcome = match &result {
+                    Ok(Ok(_)) => "ok",
+                    Ok(Err(_)) => "err",
+                    Err(_) => "error",
+                };
+                metrics::CHECK_DURATION
+                    .with_label_values(&[outcome])
+                    .observe(started.elapsed().as_secs_f64());
+                let duration_ms = started.elapsed().as_millis();
+                (Some(user_input), result, warnings, duration_ms)
+            }
+            Err(err) => (None, Err(err), Vec::new(), 0),
+        };
 
     // Clean up sandbox
     if let Err(err) = state.sandbox_manager.cleanup_sandbox(&sandbox_id).await {
         eprintln!("Failed to cleanup sandbox {}: {}", sandbox_id, err);
     }
 
+    if let (Ok(Ok(output)), Some(account)) = (&deep_result, &identity.account) {
+        state.solves.observe(account, output).await;
+    }
+
+    // Rejected requests never reached the script and are not logged
+    if let (Some(audit), Some(user_input)) = (&state.audit, &user_input) {
+        let mut entry = AuditEntry::new(
+            &state.challenge,
+            user_input,
+            &deep_result,
+            duration_ms,
+            &sandbox_id,
+        );
+        entry.team = identity.team().map(str::to_string);
+        entry.ip = Some(peer.ip().to_string());
+        if let Err(err) = audit.record(&entry).await {
+            eprintln!("Failed to write audit log: {}", err);
+        }
+    }
+
     match deep_result {
-        Ok(result) => {
-            // output is now a String, try to parse as JSON
-            match result {
-                Ok(json_str) => (
-                    StatusCode::OK,
-                    [(axum::http::header::CONTENT_TYPE, "application/json")],
-                    json_str,
-                )
-                    .into_response(),
-                Err(error_msg) => (
-                    StatusCode::OK,
-                    [(axum::http::header::CONTENT_TYPE, "text/plain")],
-                    error_msg,
-                )
-                    .into_response(),
+        Ok(result) => script_response(result, &warnings, expose_diagnostics),
+        Err(err) => error_response(StatusCode::BAD_REQUEST, &err, expose_diagnostics),
+    }
+}
+
+async fn handle_action(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Extension(identity): Extension<Identity>,
+    axum::extract::Path(name): axum::extract::Path<String>,
+    request: axum::extract::Request,
+) -> impl IntoResponse {
+    let expose_diagnostics = state.expose_diagnostics(&identity);
+    let limit = state.rune_engine.config().submission.max_body_bytes;
+    let body = match submission::read_body(request, limit).await {
+        Ok((_, body)) => body,
+        Err(err) => return error_response(StatusCode::BAD_REQUEST, &err, expose_diagnostics),
+    };
+
+    // Body is a JSON array of arguments; an empty body means no arguments
+    let args = if body.trim_ascii().is_empty() {
+        Vec::new()
+    } else {
+        match serde_json::from_slice::<Vec<serde_json::Value>>(&body) {
+            Ok(args) => args,
+            Err(e) => {
+                let err = anyhow::anyhow!("Action arguments must be a JSON array: {}", e);
+                return error_response(StatusCode::BAD_REQUEST, &err, expose_diagnostics);
             }
         }
-        Err(err) => (StatusCode::BAD_REQUEST, err.to_string()).into_response(),
+    };
+
+    let call = CallContext {
+        team: identity.team().map(str::to_string),
+        ..Default::default()
+    };
+    let _active = metrics::ActiveExecution::start();
+    let rune_engine = state.rune_engine.clone();
+    let (result, warnings) = run_blocking(move || {
+        call_script(&rune_engine, |script| {
+            script.call_action(&name, args, &call)
+        })
+    })
+    .await;
+    match result {
+        Ok(result) => script_response(result, &warnings, expose_diagnostics),
+        Err(err) => error_response(StatusCode::BAD_REQUEST, &err, expose_diagnostics),
+    }
+}
+
+/// Compile the script and make `call` on it, returning the warnings it compiled with
+fn call_script<T>(
+    rune_engine: &RuneEngine,
+    call: impl FnOnce(&CompiledScript) -> Result<T>,
+) -> (Result<T>, Vec<Diagnostic>) {
+    match rune_engine.compile() {
+        Ok(script) => (call(&script), script.diagnostics().to_vec()),
+        Err(err) => (Err(err), Vec::new()),
+    }
+}
+
+/// Response of a script call that ran: `Ok` output as JSON, `Err` output as text. Compile
+/// warnings, when exposed, go in a header so the body stays the script's own output.
+fn script_response(
+    result: Result<String, String>,
+    warnings: &[Diagnostic],
+    expose_diagnostics: bool,
+) -> Response {
+    let mut response = match result {
+        Ok(json_str) => (
+            StatusCode::OK,
+            [(axum::http::header::CONTENT_TYPE, "application/json")],
+            json_str,
+        )
+            .into_response(),
+        Err(error_msg) => (
+            StatusCode::OK,
+            [(axum::http::header::CONTENT_TYPE, "text/plain")],
+            error_msg,
+        )
+            .into_response(),
+    };
+    if expose_diagnostics && !warnings.is_empty() {
+        if let Ok(value) = HeaderValue::from_str(&ascii_json(&warnings)) {
+            response.headers_mut().insert(DIAGNOSTICS_HEADER, value);
+        }
+    }
+    response
+}
+
+/// JSON with everything outside printable ASCII escaped, so it's a valid header value
+fn ascii_json<T: Serialize>(value: &T) -> String {
+    let json = serde_json::to_string(value).unwrap_or_default();
+    let mut ascii = String::with_capacity(json.len());
+    for c in json.chars() {
+        if c.is_ascii() && c != '\x7f' {
+            ascii.push(c);
+            continue;
+        }
+        let mut units = [0u16; 2];
+        for unit in c.encode_utf16(&mut units) {
+            ascii.push_str(&format!("\\u{:04x}", unit));
+        }
+    }
+    ascii
+}
+
+/// Run a script call on the blocking pool, so a slow script can't stall the server or
+/// its shutdown. A panic in the call resurfaces in the handler as if it ran inline.
+async fn run_blocking<T: Send + 'static>(call: impl FnOnce() -> T + Send + 'static) -> T {
+    match tokio::task::spawn_blocking(call).await {
+        Ok(value) => value,
+        Err(err) => std::panic::resume_unwind(err.into_panic()),
+    }
+}
+
+/// `GET /healthz`: the process is up and serving requests
+async fn handle_healthz() -> impl IntoResponse {
+    Json(serde_json::json!({ "status": "ok" }))
+}
+
+/// `GET /readyz`: every dependency of a submission is usable, with per-check detail
+async fn handle_readyz(
+    axum::extract::State(state): axum::extract::State<AppState>,
+) -> impl IntoResponse {
+    let readiness = health::readiness(
+        &state.rune_engine,
+        &state.bucket_path,
+        state.sandbox_manager.root(),
+    )
+    .await;
+    let status = if readiness.ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(readiness))
+}
+
+/// `GET /api/admin/script`: compiler diagnostics and functions of the loaded script
+async fn handle_admin_script(
+    axum::extract::State(state): axum::extract::State<AppState>,
+) -> impl IntoResponse {
+    match state.rune_engine.inspect() {
+        Ok(info) => Json(info).into_response(),
+        Err(err) => error_response(StatusCode::INTERNAL_SERVER_ERROR, &err, true),
     }
 }
@@ -0,0 +1,219 @@
+use axum::{
+    extract::{MatchedPath, Request},
+    http::header,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, Opts, Registry,
+    TextEncoder,
+};
+use std::sync::LazyLock;
+
+/// Process-wide registry, exposed at `/metrics`
+static REGISTRY: LazyLock<Registry> = LazyLock::new(Registry::new);
+
+fn register<T: prometheus::core::Collector + Clone + 'static>(collector: T) -> T {
+    REGISTRY
+        .register(Box::new(collector.clone()))
+        .expect("metric registered twice");
+    collector
+}
+
+pub static HTTP_REQUESTS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register(
+        IntCounterVec::new(
+            Opts::new(
+                "jailbox_http_requests_total",
+                "HTTP requests by route and status",
+            ),
+            &["route", "status"],
+        )
+        .unwrap(),
+    )
+});
+
+pub static CHECK_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register(
+        HistogramVec::new(
+            HistogramOpts::new(
+                "jailbox_check_duration_seconds",
+                "Time spent in `check`, including compilation, by outcome",
+            )
+            .buckets(vec![
+                0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0,
+            ]),
+            &["outcome"],
+        )
+        .unwrap(),
+    )
+});
+
+pub static COMPILE_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register(
+        HistogramVec::new(
+            HistogramOpts::new(
+                "jailbox_rune_compile_duration_seconds",
+                "Time spent compiling the Rune script",
+            )
+            .buckets(vec![0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5]),
+            &["result"],
+        )
+        .unwrap(),
+    )
+});
+
+pub static SANDBOXES_CREATED: LazyLock<IntCounter> = LazyLock::new(|| {
+    register(IntCounter::new("jailbox_sandboxes_created_total", "Sandboxes created").unwrap())
+});
+
+pub static SANDBOXES_CLEANED: LazyLock<IntCounter> = LazyLock::new(|| {
+    register(IntCounter::new("jailbox_sandboxes_cleaned_total", "Sandboxes removed").unwrap())
+});
+
+pub static SANDBOX_FAILURES: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register(
+        IntCounterVec::new(
+            Opts::new(
+                "jailbox_sandbox_failures_total",
+                "Failed sandbox operations by operation",
+            ),
+            &["operation"],
+        )
+        .unwrap(),
+    )
+});
+
+pub static LIMIT_KILLS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register(
+        IntCounterVec::new(
+            Opts::new(
+                "jailbox_limit_kills_total",
+                "Submissions stopped by a resource limit, by limit kind",
+            ),
+            &["kind"],
+        )
+        .unwrap(),
+    )
+});
+
+pub static CPP_COMPILE_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register(
+        HistogramVec::new(
+            HistogramOpts::new(
+                "jailbox_cpp_compile_duration_seconds",
+                "Time spent compiling and linking submissions, by result",
+            )
+            .buckets(vec![0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0]),
+            &["result"],
+        )
+        .unwrap(),
+    )
+});
+
+pub static COMPILE_CACHE: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register(
+        IntCounterVec::new(
+            Opts::new(
+                "jailbox_compile_cache_requests_total",
+                "Object cache lookups by result",
+            ),
+            &["result"],
+        )
+        .unwrap(),
+    )
+});
+
+pub static COMPILE_CACHE_BYTES: LazyLock<IntGauge> = LazyLock::new(|| {
+    register(
+        IntGauge::new(
+            "jailbox_compile_cache_bytes",
+            "Size of the cached object files",
+        )
+        .unwrap(),
+    )
+});
+
+pub static COMPILE_CACHE_EVICTIONS: LazyLock<IntCounter> = LazyLock::new(|| {
+    register(
+        IntCounter::new(
+            "jailbox_compile_cache_evictions_total",
+            "Object files evicted from the cache",
+        )
+        .unwrap(),
+    )
+});
+
+pub static ACTIVE_EXECUTIONS: LazyLock<IntGauge> = LazyLock::new(|| {
+    register(
+        IntGauge::new(
+            "jailbox_active_executions",
+            "Script calls currently running",
+        )
+        .unwrap(),
+    )
+});
+
+/// Counts an execution as active for as long as the guard lives
+pub struct ActiveExecution(());
+
+impl ActiveExecution {
+    pub fn start() -> Self {
+        ACTIVE_EXECUTIONS.inc();
+        Self(())
+    }
+}
+
+impl Drop for ActiveExecution {
+    fn drop(&mut self) {
+        ACTIVE_EXECUTIONS.dec();
+    }
+}
+
+/// Middleware counting requests by matched route, so path parameters don't explode labels
+pub async fn track_requests(request: Request, next: Next) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+
+    let response = next.run(request).await;
+    HTTP_REQUESTS
+        .with_label_values(&[route.as_str(), response.status().as_str()])
+        .inc();
+    response
+}
+
+/// `GET /metrics` in the Prometheus text format
+pub async fn handle_metrics() -> Response {
+    // Register every family, even those nothing has recorded into yet
+    LazyLock::force(&HTTP_REQUESTS);
+    LazyLock::force(&CHECK_DURATION);
+    LazyLock::force(&COMPILE_DURATION);
+    LazyLock::force(&SANDBOXES_CREATED);
+    LazyLock::force(&SANDBOXES_CLEANED);
+    LazyLock::force(&SANDBOX_FAILURES);
+    LazyLock::force(&LIMIT_KILLS);
+    LazyLock::force(&CPP_COMPILE_DURATION);
+    LazyLock::force(&COMPILE_CACHE);
+    LazyLock::force(&COMPILE_CACHE_BYTES);
+    LazyLock::force(&COMPILE_CACHE_EVICTIONS);
+    LazyLock::force(&ACTIVE_EXECUTIONS);
+
+    let encoder = TextEncoder::new();
+    let mut buffer = Vec::new();
+    if let Err(err) = encoder.encode(&REGISTRY.gather(), &mut buffer) {
+        return (
+            axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+            err.to_string(),
+        )
+            .into_response();
+    }
+
+    (
+        [(header::CONTENT_TYPE, encoder.format_type().to_string())],
+        buffer,
+    )
+        .into_response()
+}
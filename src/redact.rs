@@ -0,0 +1,297 @@
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use regex::Regex;
+use std::{path::Path, sync::LazyLock};
+
+use crate::compiler::SOURCE_FILE;
+use crate::config::RedactConfig;
+use crate::template::Rendered;
+
+/// Encodings shorter than this match too much unrelated output to be worth scrubbing
+const MIN_ENCODED_LEN: usize = 6;
+
+/// `file:line:col: ` at the start of a GCC/Clang diagnostic
+static LOCATION: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^([^\s:][^:]*):(\d+):(\d+): ").unwrap());
+
+/// Directories of the compiler's own headers, whose excerpts are shown as they are
+const SYSTEM_HEADER_DIRS: &[&str] = &["/usr/include", "/usr/local/include", "/usr/lib/gcc"];
+
+/// `  12 | source text` excerpt line
+static EXCERPT: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^( *(\d+) \| )(.*)$").unwrap());
+
+/// Hides secrets and template source from compiler output shown to the script
+#[derive(Debug)]
+pub struct Redactor {
+    /// Every spelling of every secret, longest first so overlapping ones mask fully
+    needles: Vec<String>,
+    mask: String,
+}
+
+impl Redactor {
+    pub fn new(config: &RedactConfig, bucket: &Path) -> Result<Self> {
+        let mut secrets = config.secrets.clone();
+        for file in &config.secret_files {
+            let path = bucket.join(file);
+            let content = std::fs::read_to_string(&path)
+                .map_err(|e| anyhow!("Failed to read secret file {}: {}", path.display(), e))?;
+            secrets.push(content.trim().to_string());
+        }
+
+        let mut needles: Vec<String> = secrets
+            .iter()
+            .filter(|secret| !secret.is_empty())
+            .flat_map(|secret| encodings(secret))
+            .collect();
+        needles.sort_by(|a, b| b.len().cmp(&a.len()).then_with(|| a.cmp(b)));
+        needles.dedup();
+
+        Ok(Self {
+            needles,
+            mask: config.mask.clone(),
+        })
+    }
+
+    /// Mask every secret in `text`, reporting whether any was found
+    pub fn scrub(&self, text: &str) -> (String, bool) {
+        let mut text = text.to_string();
+        let mut found = false;
+        for needle in &self.needles {
+            if text.contains(needle.as_str()) {
+                text = text.replace(needle.as_str(), &self.mask);
+                found = true;
+            }
+        }
+        (text, found)
+    }
+
+    /// Mask template source quoted in compiler diagnostics, then scrub secrets. Only
+    /// excerpts of system headers are left alone: the player picks the file names the
+    /// compiler reports with `#line`, and may point them at anything it can read.
+    pub fn diagnostics(&self, stderr: &str, rendered: &Rendered) -> String {
+        let lines: Vec<&str> = rendered.source.split('\n').collect();
+        let mut quoting = Quoting::Other;
+
+        let masked: Vec<String> = stderr
+            .lines()
+            .map(|line| {
+                if let Some(location) = LOCATION.captures(line) {
+                    quoting = Quoting::of(&location[1]);
+                    return line.to_string();
+                }
+                let Some(excerpt) = EXCERPT.captures(line) else {
+                    return line.to_string();
+                };
+                match quoting {
+                    Quoting::SystemHeader => return line.to_string(),
+                    Quoting::Other => return format!("{}{}", &excerpt[1], self.mask),
+                    Quoting::Source => {}
+                }
+                let number: usize = excerpt[2].parse().unwrap_or(0);
+                let quoted = &excerpt[3];
+                match number.checked_sub(1).and_then(|index| lines.get(index)) {
+                    Some(source_line) => {
+                        let start = lines[..number - 1].iter().map(|l| l.len() + 1).sum();
+                        format!(
+                            "{}{}",
+                            &excerpt[1],
+                            self.mask_line(rendered, start, source_line, quoted)
+                        )
+                    }
+                    None => format!("{}{}", &excerpt[1], self.mask),
+                }
+            })
+            .collect();
+
+        let mut masked = masked.join("\n");
+        if stderr.ends_with('\n') {
+            masked.push('\n');
+        }
+        self.scrub(&masked).0
+    }
+
+    /// Keep the player's characters of a quoted source line and mask the template's.
+    /// Whitespace is kept so carets still line up.
+    fn mask_line(
+        &self,
+        rendered: &Rendered,
+        start: usize,
+        source_line: &str,
+        quoted: &str,
+    ) -> String {
+        // Tabs are expanded by the compiler, so the quote can't be matched byte for byte
+        if source_line.trim_end() != quoted.trim_end() {
+            let all_player = source_line
+                .char_indices()
+                .all(|(offset, c)| c.is_whitespace() || rendered.is_player(start + offset));
+            return if all_player {
+                quoted.to_string()
+            } else {
+                self.mask.clone()
+            };
+        }
+
+        quoted
+            .char_indices()
+            .map(|(offset, c)| {
+                if c.is_whitespace() || rendered.is_player(start + offset) {
+                    c
+                } else {
+                    '*'
+                }
+            })
+            .collect()
+    }
+}
+
+/// Which file the excerpts after a diagnostic's location quote
+#[derive(Clone, Copy)]
+enum Quoting {
+    /// The rendered source, masked down to the player's characters
+    Source,
+    /// A header of the compiler, shown unchanged
+    SystemHeader,
+    /// Anything else, masked whole
+    Other,
+}
+
+impl Quoting {
+    fn of(file: &str) -> Self {
+        if file == SOURCE_FILE {
+            return Quoting::Source;
+        }
+        // Resolved so `..` and symlinks can't lead out of the header directories
+        let system = Path::new(file).is_absolute()
+            && std::fs::canonicalize(file)
+                .is_ok_and(|path| SYSTEM_HEADER_DIRS.iter().any(|dir| path.starts_with(dir)));
+        if system {
+            Quoting::SystemHeader
+        } else {
+            Quoting::Other
+        }
+    }
+}
+
+/// The secret as it might show up in output: verbatim, reversed, hex and base64
+fn encodings(secret: &str) -> Vec<String> {
+    let bytes = secret.as_bytes();
+    let mut encoded = vec![
+        secret.to_string(),
+        secret.chars().rev().collect(),
+        hex::encode(bytes),
+        hex::encode_upper(bytes),
+    ];
+
+    // Base64 of a longer text only contains the characters fully determined by the
+    // secret, which depend on where the secret starts relative to a 3 byte group
+    for shift in 0..3 {
+        let mut padded = vec![0u8; shift];
+        padded.extend_from_slice(bytes);
+        let text = STANDARD.encode(&padded);
+        let first = (shift * 8).div_ceil(6);
+        let last = (shift + bytes.len()) * 8 / 6;
+        if let Some(stable) = text.get(first..last) {
+            encoded.push(stable.to_string());
+        }
+    }
+
+    encoded.retain(|needle| needle.len() >= MIN_ENCODED_LEN.min(secret.len()));
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::template::render;
+
+    const SECRET: &str = "flag{s3cr3t}";
+
+    fn redactor() -> Redactor {
+        let config = RedactConfig {
+            secrets: vec![SECRET.to_string()],
+            ..RedactConfig::default()
+        };
+        Redactor::new(&config, Path::new(".")).unwrap()
+    }
+
+    #[test]
+    fn base64_is_caught_at_any_alignment() {
+        let needles = encodings(SECRET);
+        for prefix in ["", "a", "ab", "abc", "abcd"] {
+            for suffix in ["", "z", "zz"] {
+                let text = STANDARD.encode(format!("{}{}{}", prefix, SECRET, suffix));
+                assert!(
+                    needles.iter().any(|needle| text.contains(needle.as_str())),
+                    "{:?} not caught in {}",
+                    prefix,
+                    text
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn scrubs_every_spelling() {
+        let redactor = redactor();
+        for spelling in [
+            SECRET.to_string(),
+            SECRET.chars().rev().collect(),
+            hex::encode(SECRET),
+            hex::encode_upper(SECRET),
+        ] {
+            let (text, found) = redactor.scrub(&format!("got {} here", spelling));
+            assert!(found);
+            assert_eq!(text, "got [redacted] here");
+        }
+        assert_eq!(redactor.scrub("nothing"), ("nothing".to_string(), false));
+    }
+
+    #[test]
+    fn diagnostics_keep_only_the_payload() {
+        let rendered = render("int key = 42;\nint main() { ${{user_input}} }\n", "foo();");
+        let stderr = "main.cpp:2:14: error: 'foo' was not declared\n\
+                      \x20   2 | int main() { foo(); }\n\
+                      \x20     |              ^~~\n\
+                      /usr/include/stdio.h:1:1: note: declared here\n\
+                      \x20   1 | int foo(int);\n";
+        let masked = redactor().diagnostics(stderr, &rendered);
+        assert_eq!(
+            masked,
+            "main.cpp:2:14: error: 'foo' was not declared\n\
+             \x20   2 | *** ****** * foo(); *\n\
+             \x20     |              ^~~\n\
+             /usr/include/stdio.h:1:1: note: declared here\n\
+             \x20   1 | int foo(int);\n"
+        );
+    }
+
+    #[test]
+    fn diagnostics_hide_plain_sources_and_secrets() {
+        let rendered = Rendered::plain("int main() { puts(\"flag{s3cr3t}\"); }");
+        let stderr = "main.cpp:1:14: error: bad \"flag{s3cr3t}\"\n\
+                      \x20   1 | int main() { puts(\"flag{s3cr3t}\"); }\n";
+        let masked = redactor().diagnostics(stderr, &rendered);
+        assert_eq!(
+            masked,
+            "main.cpp:1:14: error: bad \"[redacted]\"\n\
+             \x20   1 | *** ****** * ********************* *\n"
+        );
+    }
+
+    #[test]
+    fn diagnostics_mask_files_renamed_with_line_directives() {
+        let template = "int main() {\n  const char *k = \"flag{s3cr3t}\";\n${{user_input}}\n}\n";
+        let rendered = render(template, "#line 2 \"./main.cpp\"\nint x = ;");
+        // As reported by g++, quoting the template's line 2 under the player's file name
+        let stderr = "./main.cpp: In function 'int main()':\n\
+                      ./main.cpp:2:9: error: expected primary-expression before ';' token\n\
+                      \x20   2 |   const char *k = \"flag{s3cr3t}\";\n\
+                      \x20     |         ^\n\
+                      /usr/include/../../tmp/flag.txt:1:1: error: 'flag' does not name a type\n\
+                      \x20   1 | flag{s3cr3t}\n";
+        let redactor = Redactor::new(&RedactConfig::default(), Path::new(".")).unwrap();
+        let masked = redactor.diagnostics(stderr, &rendered);
+        assert!(!masked.contains("s3cr3t"), "{}", masked);
+        assert!(masked.contains("    2 | [redacted]\n      |         ^\n"));
+    }
+}
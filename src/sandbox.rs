@@ -1,39 +1,602 @@
 use anyhow::{anyhow, Result};
+use serde::Serialize;
 use std::{
-    collections::HashMap,
-    path::{Path, PathBuf},
+    collections::{HashMap, HashSet},
+    fs,
+    io::{self, Read},
+    os::{
+        fd::AsRawFd,
+        unix::{fs::PermissionsExt, process::CommandExt},
+    },
+    path::{Component, Path, PathBuf},
+    process::{Command, Stdio},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
 };
 use tempfile::TempDir;
 use tokio::sync::RwLock;
 
+use crate::metrics;
+
+/// Directory under the system temp dir that holds every sandbox by default
+const SANDBOX_ROOT_DIR: &str = "jailbox-sandboxes";
+
+/// Sandboxes are named `jailbox-<pid>-<random>`, so a sweep only touches its own
+const SANDBOX_PREFIX: &str = "jailbox-";
+
+/// Suffix of the file next to a sandbox listing the process groups started in it, which
+/// lets a sweep after a crash kill only what this server started
+const GROUPS_SUFFIX: &str = ".pgids";
+
+/// How often a running process's sandbox is measured against its quota
+const QUOTA_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Disk limits applied to sandboxes
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SandboxLimits {
+    /// Bytes a single sandbox may hold while a process runs in it
+    pub quota: Option<u64>,
+    /// Bytes all sandboxes together may hold before new ones are refused
+    pub disk_cap: Option<u64>,
+}
+
+/// New sandboxes are refused while the sandbox root is over its disk cap
+#[derive(Debug)]
+pub struct ServerBusy {
+    pub used: u64,
+    pub cap: u64,
+}
+
+impl std::fmt::Display for ServerBusy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Server busy: sandboxes use {} of {} bytes, try again later",
+            self.used, self.cap
+        )
+    }
+}
+
+impl std::error::Error for ServerBusy {}
+
+/// Process groups started inside a sandbox, so they can be killed together with it.
+/// They are also written to a record file outside the sandbox, for sweeps after a crash.
+#[derive(Debug, Default)]
+pub struct ProcessGroups {
+    groups: Mutex<HashSet<u32>>,
+    record: Option<PathBuf>,
+}
+
+impl ProcessGroups {
+    fn recorded_in(record: PathBuf) -> Self {
+        Self {
+            groups: Mutex::default(),
+            record: Some(record),
+        }
+    }
+
+    fn insert(&self, pgid: u32) {
+        let mut groups = self.groups.lock().unwrap();
+        groups.insert(pgid);
+        self.write_record(&groups);
+    }
+
+    fn remove(&self, pgid: u32) {
+        let mut groups = self.groups.lock().unwrap();
+        groups.remove(&pgid);
+        self.write_record(&groups);
+    }
+
+    fn write_record(&self, groups: &HashSet<u32>) {
+        if let Some(record) = &self.record {
+            let lines: String = groups.iter().map(|pgid| format!("{}\n", pgid)).collect();
+            if let Err(err) = fs::write(record, lines) {
+                eprintln!(
+                    "Failed to record process groups in {}: {}",
+                    record.display(),
+                    err
+                );
+            }
+        }
+    }
+
+    /// SIGKILL every tracked group, returning how many were still registered
+    pub fn kill_all(&self) -> usize {
+        let mut groups = self.groups.lock().unwrap();
+        let killed = std::mem::take(&mut *groups);
+        for &pgid in &killed {
+            kill_group(pgid);
+        }
+        self.write_record(&groups);
+        killed.len()
+    }
+}
+
+/// Process groups listed in a sandbox's record file
+fn recorded_groups(sandbox: &Path) -> HashSet<u32> {
+    fs::read_to_string(groups_record(sandbox))
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| line.trim().parse().ok())
+        .collect()
+}
+
+fn groups_record(sandbox: &Path) -> PathBuf {
+    let mut record = sandbox.as_os_str().to_os_string();
+    record.push(GROUPS_SUFFIX);
+    PathBuf::from(record)
+}
+
+fn kill_group(pgid: u32) {
+    // SAFETY: killpg has no memory safety requirements; a stale group only yields ESRCH
+    unsafe {
+        libc::killpg(pgid as libc::pid_t, libc::SIGKILL);
+    }
+}
+
+/// Result of a program run inside a sandbox
+pub struct ProcessOutput {
+    /// Exit code, `None` when the process was killed by a signal
+    pub status: Option<i32>,
+    /// Up to `MAX_CAPTURED_BYTES` of stderr
+    pub stderr: Vec<u8>,
+    pub timed_out: bool,
+}
+
+/// Run `program` in the sandbox as the leader of a new process group, killing the whole
+/// group once `timeout` passes or the sandbox outgrows its quota. The group is tracked in
+/// the sandbox's process groups while it runs. Stdout is discarded.
+pub fn run_process(
+    sandbox: &SandboxHandle,
+    program: &str,
+    args: &[String],
+    timeout: Duration,
+) -> io::Result<ProcessOutput> {
+    run(sandbox, program, args, Stdio::null(), timeout)
+}
+
+/// Like `run_process`, but with stdout written to `stdout` in the sandbox, where the
+/// quota bounds it
+pub fn run_process_to(
+    sandbox: &SandboxHandle,
+    program: &str,
+    args: &[String],
+    stdout: &str,
+    timeout: Duration,
+) -> io::Result<ProcessOutput> {
+    let file = fs::File::create(sandbox.path.join(stdout))?;
+    run(sandbox, program, args, Stdio::from(file), timeout)
+}
+
+fn run(
+    sandbox: &SandboxHandle,
+    program: &str,
+    args: &[String],
+    stdout: Stdio,
+    timeout: Duration,
+) -> io::Result<ProcessOutput> {
+    let dir = sandbox.path.as_path();
+    let processes = &sandbox.processes;
+    let mut child = Command::new(program)
+        .args(args)
+        .current_dir(dir)
+        .stdin(Stdio::null())
+        .stdout(stdout)
+        .stderr(Stdio::piped())
+        .process_group(0)
+        .spawn()?;
+    let pgid = child.id();
+    processes.insert(pgid);
+
+    // Read stderr between polls rather than on a thread, so nothing waits on a pipe a
+    // process that left the group with `setsid` could hold open forever
+    let mut stderr = child.stderr.take().map(Capture::new).transpose()?;
+
+    let started = Instant::now();
+    let mut last_quota_check = started;
+    let mut timed_out = false;
+    loop {
+        if let Some(capture) = &mut stderr {
+            capture.read_available();
+        }
+        if leader_exited(pgid)? {
+            break;
+        }
+        if started.elapsed() >= timeout {
+            timed_out = true;
+            metrics::LIMIT_KILLS.with_label_values(&["timeout"]).inc();
+            break;
+        }
+        if let Some(quota) = sandbox.quota {
+            if last_quota_check.elapsed() >= QUOTA_CHECK_INTERVAL {
+                last_quota_check = Instant::now();
+                if dir_size(dir) > quota {
+                    metrics::LIMIT_KILLS
+                        .with_label_values(&["disk_quota"])
+                        .inc();
+                    break;
+                }
+            }
+        }
+        std::thread::sleep(Duration::from_millis(5));
+    }
+
+    // Kill what's left of the group while the leader is still unreaped, so the group id
+    // can't have been reused by then
+    kill_group(pgid);
+    let status = child.wait()?;
+    processes.remove(pgid);
+
+    Ok(ProcessOutput {
+        status: status.code(),
+        stderr: stderr
+            .map(|mut capture| {
+                capture.read_available();
+                capture.buffer
+            })
+            .unwrap_or_default(),
+        timed_out,
+    })
+}
+
+/// Whether the group leader has exited, without reaping it
+fn leader_exited(pid: u32) -> io::Result<bool> {
+    // SAFETY: waitid only writes to `info`, which is a valid siginfo_t
+    unsafe {
+        let mut info: libc::siginfo_t = std::mem::zeroed();
+        let flags = libc::WEXITED | libc::WNOHANG | libc::WNOWAIT;
+        if libc::waitid(libc::P_PID, pid as libc::id_t, &mut info, flags) == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(info.si_pid() != 0)
+    }
+}
+
+/// Bytes of a process's stderr kept; the rest is read and dropped
+const MAX_CAPTURED_BYTES: usize = 1024 * 1024;
+
+/// Non-blocking reader for a child's pipe
+struct Capture<R> {
+    pipe: R,
+    buffer: Vec<u8>,
+}
+
+impl<R: Read + AsRawFd> Capture<R> {
+    fn new(pipe: R) -> io::Result<Self> {
+        // SAFETY: fcntl on a descriptor the pipe owns for as long as it lives
+        unsafe {
+            let fd = pipe.as_raw_fd();
+            let flags = libc::fcntl(fd, libc::F_GETFL);
+            if flags == -1 || libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) == -1 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(Self {
+            pipe,
+            buffer: Vec::new(),
+        })
+    }
+
+    /// Read whatever is in the pipe now, keeping it up to the cap
+    fn read_available(&mut self) {
+        let mut chunk = [0u8; 8192];
+        loop {
+            match self.pipe.read(&mut chunk) {
+                Ok(0) => return,
+                Ok(read) => {
+                    let room = MAX_CAPTURED_BYTES.saturating_sub(self.buffer.len());
+                    self.buffer.extend_from_slice(&chunk[..read.min(room)]);
+                }
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(_) => return,
+            }
+        }
+    }
+}
+
 pub struct Sandbox {
     temp_dir: TempDir,
+    processes: Arc<ProcessGroups>,
+    created: Instant,
 }
 
 impl Sandbox {
-    pub fn new() -> Result<Self> {
-        let temp_dir = TempDir::new()?;
-        Ok(Self { temp_dir })
+    /// Create a sandbox directory in `root`, named after this process so a later sweep
+    /// can tell leftovers of dead runs from live sandboxes of another instance
+    pub fn new(root: &Path) -> Result<Self> {
+        let temp_dir = tempfile::Builder::new()
+            .prefix(&format!("{}{}-", SANDBOX_PREFIX, std::process::id()))
+            .tempdir_in(root)?;
+        let processes = ProcessGroups::recorded_in(groups_record(temp_dir.path()));
+        Ok(Self {
+            temp_dir,
+            processes: Arc::new(processes),
+            created: Instant::now(),
+        })
     }
 
     pub fn path(&self) -> &Path {
         self.temp_dir.path()
     }
+
+    pub fn handle(&self, limits: SandboxLimits) -> SandboxHandle {
+        SandboxHandle {
+            path: self.path().to_path_buf(),
+            processes: self.processes.clone(),
+            quota: limits.quota,
+            disk_cap: limits.disk_cap,
+        }
+    }
+
+    /// Kill its processes and remove its directory, returning how many process groups
+    /// were still running
+    pub fn destroy(self) -> io::Result<usize> {
+        let groups = self.processes.kill_all();
+        make_writable(self.path());
+        let record = groups_record(self.path());
+        self.temp_dir.close()?;
+        let _ = fs::remove_file(record);
+        Ok(groups)
+    }
+}
+
+/// What the janitor or a sweep gave back
+#[derive(Debug, Default, Serialize)]
+pub struct Reclaimed {
+    pub sandboxes: usize,
+    pub process_groups: usize,
+    pub bytes: u64,
+}
+
+impl Reclaimed {
+    pub fn is_empty(&self) -> bool {
+        self.sandboxes == 0 && self.process_groups == 0
+    }
+}
+
+/// Total size of regular files below `path`, without following symlinks
+fn dir_size(path: &Path) -> u64 {
+    let Ok(entries) = fs::read_dir(path) else {
+        return 0;
+    };
+    entries
+        .filter_map(Result::ok)
+        .map(|entry| match entry.file_type() {
+            Ok(kind) if kind.is_dir() => dir_size(&entry.path()),
+            Ok(kind) if kind.is_file() => entry.metadata().map(|m| m.len()).unwrap_or(0),
+            _ => 0,
+        })
+        .sum()
+}
+
+/// Bytes copying `source`, relative to `bucket`, would write
+pub fn bucket_size(bucket: &Path, source: &str) -> u64 {
+    let path = bucket.join(source);
+    if path.is_dir() {
+        dir_size(&path)
+    } else {
+        fs::metadata(&path).map(|m| m.len()).unwrap_or(0)
+    }
+}
+
+/// Copy `source`, a file or directory relative to `bucket`, to `dest`. Copied files and
+/// directories are read-only, so nothing in a copied directory can be replaced by a process
+/// that respects permissions; the sandbox runs as the server's user, which could still
+/// change them back. Anything resolving outside the bucket, through `..` or a symlink, is
+/// refused.
+pub fn copy_from_bucket(bucket: &Path, source: &str, dest: &Path) -> io::Result<()> {
+    if !Path::new(source)
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("Access to this path is not allowed: {}", source),
+        ));
+    }
+
+    let bucket = bucket.canonicalize()?;
+    copy_confined(&bucket, &bucket.join(source), dest)
+}
+
+fn copy_confined(bucket: &Path, source: &Path, dest: &Path) -> io::Result<()> {
+    let resolved = source.canonicalize().map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!("Failed to resolve {}: {}", source.display(), e),
+        )
+    })?;
+    if !resolved.starts_with(bucket) {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("Access to this path is not allowed: {}", source.display()),
+        ));
+    }
+
+    if resolved.is_dir() {
+        // Following directory symlinks could loop, and nothing needs them
+        if source.is_symlink() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Symlinked directories are not copied: {}", source.display()),
+            ));
+        }
+        // Copying again over an earlier copy needs it writable while it's filled
+        if dest.is_dir() {
+            fs::set_permissions(dest, fs::Permissions::from_mode(0o755))?;
+        }
+        fs::create_dir_all(dest)?;
+        for entry in fs::read_dir(source)? {
+            let entry = entry?;
+            copy_confined(bucket, &entry.path(), &dest.join(entry.file_name()))?;
+        }
+        return fs::set_permissions(dest, fs::Permissions::from_mode(0o555));
+    }
+
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // A previous read-only copy would make `fs::copy` fail
+    if dest.is_file() {
+        fs::remove_file(dest)?;
+    }
+    fs::copy(&resolved, dest)?;
+    fs::set_permissions(dest, fs::Permissions::from_mode(0o444))
+}
+
+/// Give the owner write access to every directory below `path`, which removing read-only
+/// copies needs. Symlinks aren't followed.
+fn make_writable(path: &Path) {
+    let Ok(metadata) = fs::symlink_metadata(path) else {
+        return;
+    };
+    if !metadata.is_dir() {
+        return;
+    }
+    let _ = fs::set_permissions(
+        path,
+        fs::Permissions::from_mode(metadata.permissions().mode() | 0o700),
+    );
+    for entry in fs::read_dir(path)
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+    {
+        make_writable(&entry.path());
+    }
+}
+
+/// Process groups of processes whose working directory is below `dir`, found via /proc
+fn groups_working_in(dir: &Path) -> HashSet<u32> {
+    let Ok(entries) = fs::read_dir("/proc") else {
+        return HashSet::new();
+    };
+    entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| entry.file_name().to_str()?.parse::<u32>().ok())
+        .filter(|pid| {
+            fs::read_link(format!("/proc/{}/cwd", pid)).is_ok_and(|cwd| cwd.starts_with(dir))
+        })
+        // SAFETY: getpgid has no memory safety requirements
+        .map(|pid| unsafe { libc::getpgid(pid as libc::pid_t) })
+        .filter(|&pgid| pgid > 0)
+        .map(|pgid| pgid as u32)
+        .collect()
+}
+
+fn process_alive(pid: u32) -> bool {
+    // SAFETY: signal 0 only checks that the process exists
+    unsafe { libc::kill(pid as libc::pid_t, 0) == 0 }
+}
+
+/// What a script call needs to work inside a sandbox
+#[derive(Clone, Debug)]
+pub struct SandboxHandle {
+    pub path: PathBuf,
+    pub processes: Arc<ProcessGroups>,
+    pub quota: Option<u64>,
+    /// Bytes all sandboxes in the same root may hold together
+    pub disk_cap: Option<u64>,
+}
+
+impl SandboxHandle {
+    /// Refuse to add `bytes` when they would take the sandbox past its quota, or all
+    /// sandboxes past the disk cap, which fails with a `ServerBusy` inside
+    pub fn reserve(&self, bytes: u64) -> io::Result<()> {
+        if let Some(quota) = self.quota {
+            let used = dir_size(&self.path);
+            if used.saturating_add(bytes) > quota {
+                return Err(io::Error::new(
+                    io::ErrorKind::QuotaExceeded,
+                    format!(
+                        "Writing {} bytes would take the sandbox past its {} byte quota",
+                        bytes, quota
+                    ),
+                ));
+            }
+        }
+        if let (Some(cap), Some(root)) = (self.disk_cap, self.path.parent()) {
+            let used = dir_size(root);
+            if used.saturating_add(bytes) > cap {
+                return Err(io::Error::other(ServerBusy { used, cap }));
+            }
+        }
+        Ok(())
+    }
+
+    /// Write `name` in the sandbox, within its quota
+    pub fn write(&self, name: &str, contents: impl AsRef<[u8]>) -> io::Result<()> {
+        let path = self.path.join(name);
+        let contents = contents.as_ref();
+        let replaced = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+        self.reserve((contents.len() as u64).saturating_sub(replaced))?;
+        fs::write(path, contents)
+    }
 }
 
 pub struct SandboxManager {
+    root: PathBuf,
+    limits: SandboxLimits,
+    /// Bucket and the directory in it copied into every new sandbox
+    template: Option<(PathBuf, String)>,
     sandboxes: RwLock<HashMap<String, Sandbox>>,
+    /// Ids the janitor destroyed before their owner cleaned them up
+    expired: Mutex<HashSet<String>>,
 }
 
 impl SandboxManager {
-    pub fn new() -> Self {
-        Self {
+    /// Default sandbox root, under the system temp dir
+    pub fn default_root() -> PathBuf {
+        std::env::temp_dir().join(SANDBOX_ROOT_DIR)
+    }
+
+    pub fn new(root: PathBuf, limits: SandboxLimits) -> Result<Self> {
+        fs::create_dir_all(&root)
+            .map_err(|e| anyhow!("Failed to create sandbox root {}: {}", root.display(), e))?;
+
+        Ok(Self {
+            root,
+            limits,
+            template: None,
             sandboxes: RwLock::new(HashMap::new()),
+            expired: Mutex::new(HashSet::new()),
+        })
+    }
+
+    /// Pre-populate every new sandbox with the contents of a bucket directory
+    pub fn with_template(mut self, bucket: &Path, dir: &str) -> Result<Self> {
+        let path = bucket.join(dir);
+        if !path.is_dir() {
+            return Err(anyhow!(
+                "Sandbox template {} is not a directory",
+                path.display()
+            ));
+        }
+        if !path.canonicalize()?.starts_with(bucket.canonicalize()?) {
+            return Err(anyhow!(
+                "Sandbox template {} is outside the data bucket",
+                path.display()
+            ));
         }
+        self.template = Some((bucket.to_path_buf(), dir.to_string()));
+        Ok(self)
     }
 
-    pub async fn create_sandbox(&self, id: &str) -> Result<Sandbox> {
-        let sandbox = Sandbox::new()?;
+    /// Create a sandbox owned by the manager and return a handle to it
+    pub async fn create_sandbox(&self, id: &str) -> Result<SandboxHandle> {
+        if let Some(cap) = self.limits.disk_cap {
+            let root = self.root.clone();
+            let used = tokio::task::spawn_blocking(move || dir_size(&root)).await?;
+            if used >= cap {
+                metrics::SANDBOX_FAILURES
+                    .with_label_values(&["disk_cap"])
+                    .inc();
+                return Err(ServerBusy { used, cap }.into());
+            }
+        }
+
+        let sandbox = self.create_unmanaged()?;
+        let handle = sandbox.handle(self.limits);
 
         // Add sandbox to manager
         {
@@ -41,23 +604,329 @@ impl SandboxManager {
             sandboxes.insert(id.to_string(), sandbox);
         }
 
-        // Return a new sandbox instance for execution
-        Sandbox::new()
+        Ok(handle)
+    }
+
+    /// Create a sandbox with the template copied in, left to the caller to destroy
+    pub fn create_unmanaged(&self) -> Result<Sandbox> {
+        let sandbox = Sandbox::new(&self.root).inspect_err(|_| {
+            metrics::SANDBOX_FAILURES
+                .with_label_values(&["create"])
+                .inc();
+        })?;
+        if let Some((bucket, dir)) = &self.template {
+            // The template's contents stay read-only, the sandbox itself writable
+            let copied = copy_from_bucket(bucket, dir, sandbox.path()).and_then(|()| {
+                fs::set_permissions(sandbox.path(), fs::Permissions::from_mode(0o700))
+            });
+            if let Err(err) = copied {
+                metrics::SANDBOX_FAILURES
+                    .with_label_values(&["template"])
+                    .inc();
+                let _ = sandbox.destroy();
+                return Err(anyhow!("Failed to copy sandbox template: {}", err));
+            }
+        }
+        metrics::SANDBOXES_CREATED.inc();
+        Ok(sandbox)
     }
 
     pub async fn cleanup_sandbox(&self, id: &str) -> Result<()> {
-        let mut sandboxes = self.sandboxes.write().await;
+        let removed = self.sandboxes.write().await.remove(id);
 
-        if let Some(_sandbox) = sandboxes.remove(id) {
-            // TempDir will be automatically cleaned up when dropped
+        if let Some(sandbox) = removed {
+            // Killing and removing blocks, keep it off the runtime
+            tokio::task::spawn_blocking(move || sandbox.destroy())
+                .await?
+                .inspect_err(|_| {
+                    metrics::SANDBOX_FAILURES
+                        .with_label_values(&["cleanup"])
+                        .inc();
+                })?;
+            metrics::SANDBOXES_CLEANED.inc();
+            Ok(())
+        } else if self.expired.lock().unwrap().remove(id) {
             Ok(())
         } else {
+            metrics::SANDBOX_FAILURES
+                .with_label_values(&["cleanup"])
+                .inc();
             Err(anyhow!("Sandbox {} not found", id))
         }
     }
 
-    pub async fn get_sandbox(&self, id: &str) -> Option<PathBuf> {
-        let sandboxes = self.sandboxes.read().await;
-        sandboxes.get(id).map(|s| s.path().to_path_buf())
+    /// Kill every sandbox's processes and remove every directory, for shutdown.
+    /// Returns how many sandboxes were still alive.
+    pub async fn cleanup_all(&self) -> usize {
+        let sandboxes = std::mem::take(&mut *self.sandboxes.write().await);
+        let count = sandboxes.len();
+
+        let destroyed = tokio::task::spawn_blocking(move || {
+            for (id, sandbox) in sandboxes {
+                match sandbox.destroy() {
+                    Ok(_) => metrics::SANDBOXES_CLEANED.inc(),
+                    Err(err) => {
+                        metrics::SANDBOX_FAILURES
+                            .with_label_values(&["cleanup"])
+                            .inc();
+                        eprintln!("Failed to cleanup sandbox {}: {}", id, err);
+                    }
+                }
+            }
+        })
+        .await;
+        if let Err(err) = destroyed {
+            eprintln!("Failed to cleanup sandboxes: {}", err);
+        }
+
+        count
+    }
+
+    /// Destroy sandboxes older than `ttl`, killing whatever still runs in them
+    pub async fn expire(&self, ttl: Duration) -> Reclaimed {
+        let expired = {
+            let mut sandboxes = self.sandboxes.write().await;
+            let ids = sandboxes
+                .iter()
+                .filter(|(_, sandbox)| sandbox.created.elapsed() >= ttl)
+                .map(|(id, _)| id.clone())
+                .collect::<Vec<_>>();
+            ids.into_iter()
+                .filter_map(|id| sandboxes.remove(&id).map(|sandbox| (id, sandbox)))
+                .collect::<Vec<_>>()
+        };
+
+        self.expired
+            .lock()
+            .unwrap()
+            .extend(expired.iter().map(|(id, _)| id.clone()));
+
+        // Measuring and removing directories blocks, keep it off the runtime
+        tokio::task::spawn_blocking(move || {
+            let mut reclaimed = Reclaimed::default();
+            for (id, sandbox) in expired {
+                let bytes = dir_size(sandbox.path());
+                match sandbox.destroy() {
+                    Ok(groups) => {
+                        if groups > 0 {
+                            metrics::LIMIT_KILLS
+                                .with_label_values(&["ttl"])
+                                .inc_by(groups as u64);
+                        }
+                        metrics::SANDBOXES_CLEANED.inc();
+                        reclaimed.sandboxes += 1;
+                        reclaimed.process_groups += groups;
+                        reclaimed.bytes += bytes;
+                    }
+                    Err(err) => {
+                        metrics::SANDBOX_FAILURES
+                            .with_label_values(&["expire"])
+                            .inc();
+                        eprintln!("Failed to remove expired sandbox {}: {}", id, err);
+                    }
+                }
+            }
+            reclaimed
+        })
+        .await
+        .unwrap_or_default()
+    }
+
+    /// Remove directories in the sandbox root left by runs that are no longer alive,
+    /// killing the orphaned process groups they recorded that still work in them
+    pub fn sweep_stale(&self) -> Result<Reclaimed> {
+        let mut reclaimed = Reclaimed::default();
+
+        for entry in fs::read_dir(&self.root)?.filter_map(Result::ok) {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let owner = name
+                .strip_prefix(SANDBOX_PREFIX)
+                .and_then(|rest| rest.split_once('-'))
+                .and_then(|(pid, _)| pid.parse::<u32>().ok());
+
+            // Anything not named like a sandbox was not created by us, and sandboxes of
+            // this process or of another live instance are not stale
+            let Some(owner) = owner else {
+                continue;
+            };
+            if owner == std::process::id() || process_alive(owner) {
+                continue;
+            }
+
+            let path = entry.path();
+            if let Some(sandbox) = name.strip_suffix(GROUPS_SUFFIX) {
+                // A record is removed with its sandbox, or on its own once that is gone
+                if self.root.join(sandbox).exists() {
+                    continue;
+                }
+                let _ = fs::remove_file(&path);
+                continue;
+            }
+
+            // A recorded group id may have been reused since its owner died, so only
+            // groups that still have a process working in the sandbox are killed
+            let working = groups_working_in(&path);
+            for pgid in recorded_groups(&path).intersection(&working) {
+                kill_group(*pgid);
+                reclaimed.process_groups += 1;
+            }
+            let _ = fs::remove_file(groups_record(&path));
+
+            let bytes = dir_size(&path);
+            let removed = if path.is_dir() {
+                make_writable(&path);
+                fs::remove_dir_all(&path)
+            } else {
+                fs::remove_file(&path)
+            };
+            match removed {
+                Ok(()) => {
+                    reclaimed.sandboxes += 1;
+                    reclaimed.bytes += bytes;
+                }
+                Err(err) => {
+                    metrics::SANDBOX_FAILURES
+                        .with_label_values(&["sweep"])
+                        .inc();
+                    eprintln!("Failed to remove stale sandbox {}: {}", path.display(), err);
+                }
+            }
+        }
+
+        Ok(reclaimed)
+    }
+
+    /// Periodically expire sandboxes older than `ttl`
+    pub fn spawn_janitor(self: Arc<Self>, ttl: Duration) {
+        // Check often enough that nothing outlives its TTL by more than a quarter
+        let interval = (ttl / 4).clamp(Duration::from_secs(1), Duration::from_secs(60));
+
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                let reclaimed = self.expire(ttl).await;
+                if !reclaimed.is_empty() {
+                    println!(
+                        "Janitor: expired {} sandbox(es), killed {} process group(s), freed {} bytes",
+                        reclaimed.sandboxes, reclaimed.process_groups, reclaimed.bytes
+                    );
+                }
+            }
+        });
+    }
+
+    /// Directory new sandboxes are created in
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::process::ExitStatusExt;
+
+    fn run_sh(sandbox: &Sandbox, script: &str) -> ProcessOutput {
+        let args = ["-c".to_string(), script.to_string()];
+        run_process(
+            &sandbox.handle(SandboxLimits::default()),
+            "sh",
+            &args,
+            Duration::from_secs(5),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn detached_processes_do_not_hold_the_call() {
+        let root = tempfile::tempdir().unwrap();
+        let sandbox = Sandbox::new(root.path()).unwrap();
+        let started = Instant::now();
+        let output = run_sh(&sandbox, "setsid sleep 30 & echo started >&2");
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(output.status, Some(0));
+        assert!(!output.timed_out);
+    }
+
+    #[test]
+    fn stderr_is_capped() {
+        let root = tempfile::tempdir().unwrap();
+        let sandbox = Sandbox::new(root.path()).unwrap();
+        let output = run_sh(&sandbox, "head -c 3000000 /dev/zero >&2");
+        assert_eq!(output.status, Some(0));
+        assert_eq!(output.stderr.len(), MAX_CAPTURED_BYTES);
+    }
+
+    #[test]
+    fn sweep_only_removes_dead_sandboxes() {
+        let root = tempfile::tempdir().unwrap();
+        // Above the largest pid Linux hands out, so never alive
+        let dead = root.path().join("jailbox-4194305-abc");
+        let foreign = root.path().join("4194305-abc");
+        fs::create_dir(&dead).unwrap();
+        fs::create_dir(&foreign).unwrap();
+        let live = Sandbox::new(root.path()).unwrap();
+
+        // Both work in the dead sandbox, but only the first was started by its owner
+        let spawn = || {
+            Command::new("sleep")
+                .arg("30")
+                .current_dir(&dead)
+                .process_group(0)
+                .spawn()
+                .unwrap()
+        };
+        let (mut recorded, mut stranger) = (spawn(), spawn());
+        fs::write(groups_record(&dead), format!("{}\n", recorded.id())).unwrap();
+
+        let manager = SandboxManager::new(root.path().to_path_buf(), SandboxLimits::default());
+        let reclaimed = manager.unwrap().sweep_stale().unwrap();
+        assert_eq!(reclaimed.sandboxes, 1);
+        assert_eq!(reclaimed.process_groups, 1);
+        assert!(!dead.exists());
+        assert!(!groups_record(&dead).exists());
+        assert!(foreign.exists());
+        assert!(live.path().exists());
+
+        assert!(recorded.wait().unwrap().signal().is_some());
+        assert!(stranger.try_wait().unwrap().is_none());
+        stranger.kill().unwrap();
+        stranger.wait().unwrap();
+    }
+
+    #[test]
+    fn writes_stay_within_the_quota() {
+        let root = tempfile::tempdir().unwrap();
+        let sandbox = Sandbox::new(root.path()).unwrap();
+        let handle = sandbox.handle(SandboxLimits {
+            quota: Some(100),
+            disk_cap: None,
+        });
+        handle.write("a", [0; 60]).unwrap();
+        handle.write("a", [0; 90]).unwrap();
+        let err = handle.write("b", [0; 20]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::QuotaExceeded);
+        assert!(!sandbox.path().join("b").exists());
+    }
+
+    #[test]
+    fn copies_are_read_only_and_still_removed() {
+        let bucket = tempfile::tempdir().unwrap();
+        fs::create_dir_all(bucket.path().join("data/inner")).unwrap();
+        fs::write(bucket.path().join("data/inner/checker.txt"), "ok").unwrap();
+        let root = tempfile::tempdir().unwrap();
+        let sandbox = Sandbox::new(root.path()).unwrap();
+        let dest = sandbox.path().join("data");
+
+        copy_from_bucket(bucket.path(), "data", &dest).unwrap();
+        copy_from_bucket(bucket.path(), "data", &dest).unwrap();
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&dest.join("inner")), 0o555);
+        assert_eq!(mode(&dest.join("inner/checker.txt")), 0o444);
+
+        let path = sandbox.path().to_path_buf();
+        sandbox.destroy().unwrap();
+        assert!(!path.exists());
     }
 }
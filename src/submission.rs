@@ -0,0 +1,303 @@
+use anyhow::Result;
+use axum::{
+    body::{Body, Bytes},
+    extract::{FromRequest, Multipart, Request},
+    http::{header::CONTENT_LENGTH, header::CONTENT_TYPE, StatusCode},
+};
+use http_body_util::LengthLimitError;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::{fmt, io};
+
+use crate::config::{InvalidUtf8, SubmissionConfig};
+use crate::engine::UserInput;
+use crate::metrics;
+use crate::sandbox::{SandboxHandle, ServerBusy};
+
+/// Sandbox subdirectory receiving uploaded files
+const UPLOAD_DIR: &str = "uploads";
+
+/// A request rejected at the HTTP layer, before reaching the script
+#[derive(Debug, Serialize)]
+#[serde(tag = "code", rename_all = "snake_case")]
+pub enum SubmissionError {
+    PayloadTooLarge {
+        limit: usize,
+    },
+    UnsupportedContentType {
+        content_type: String,
+        allowed: Vec<String>,
+    },
+    InvalidUtf8 {
+        /// Byte offset of the first invalid sequence
+        valid_up_to: usize,
+    },
+    /// The body could not be read, such as when the client aborted the upload
+    BodyUnreadable {
+        reason: String,
+    },
+    /// The body doesn't parse as its content type says
+    InvalidBody {
+        reason: String,
+    },
+    /// An uploaded file doesn't fit in the sandbox's disk quota
+    UploadTooLarge {
+        reason: String,
+    },
+}
+
+impl SubmissionError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            SubmissionError::PayloadTooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+            SubmissionError::UnsupportedContentType { .. } => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            SubmissionError::InvalidUtf8 { .. } => StatusCode::BAD_REQUEST,
+            SubmissionError::BodyUnreadable { .. } => StatusCode::BAD_REQUEST,
+            SubmissionError::InvalidBody { .. } => StatusCode::BAD_REQUEST,
+            SubmissionError::UploadTooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+        }
+    }
+}
+
+impl fmt::Display for SubmissionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SubmissionError::PayloadTooLarge { limit } => {
+                write!(f, "Request body exceeds the limit of {} bytes", limit)
+            }
+            SubmissionError::UnsupportedContentType {
+                content_type,
+                allowed,
+            } => write!(
+                f,
+                "Content type `{}` is not accepted, expected one of: {}",
+                content_type,
+                allowed.join(", ")
+            ),
+            SubmissionError::InvalidUtf8 { valid_up_to } => write!(
+                f,
+                "Request body is not valid UTF-8 (invalid byte at offset {})",
+                valid_up_to
+            ),
+            SubmissionError::BodyUnreadable { reason } => {
+                write!(f, "Failed to read request body: {}", reason)
+            }
+            SubmissionError::InvalidBody { reason } => write!(f, "{}", reason),
+            SubmissionError::UploadTooLarge { reason } => {
+                write!(f, "Upload rejected: {}", reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SubmissionError {}
+
+fn invalid_body(reason: String) -> SubmissionError {
+    SubmissionError::InvalidBody { reason }
+}
+
+/// Media type of the request without parameters, lowercased
+fn media_type(request: &Request) -> String {
+    request
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .unwrap_or("")
+        .trim()
+        .to_ascii_lowercase()
+}
+
+/// Buffer the request body, failing fast on an oversized `Content-Length`
+pub async fn read_body(request: Request, limit: usize) -> Result<(Request<()>, Bytes)> {
+    let declared = request
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<usize>().ok());
+    if declared.is_some_and(|length| length > limit) {
+        metrics::LIMIT_KILLS.with_label_values(&["body_size"]).inc();
+        return Err(SubmissionError::PayloadTooLarge { limit }.into());
+    }
+
+    let (parts, body) = request.into_parts();
+    let bytes = axum::body::to_bytes(body, limit).await.map_err(|err| {
+        if exceeds_limit(&err) {
+            metrics::LIMIT_KILLS.with_label_values(&["body_size"]).inc();
+            SubmissionError::PayloadTooLarge { limit }
+        } else {
+            SubmissionError::BodyUnreadable {
+                reason: err.to_string(),
+            }
+        }
+    })?;
+    Ok((Request::from_parts(parts, ()), bytes))
+}
+
+/// Whether reading a body failed on its length limit rather than on the connection
+fn exceeds_limit(err: &axum::Error) -> bool {
+    let mut source: Option<&(dyn std::error::Error + 'static)> = Some(err);
+    while let Some(err) = source {
+        if err.is::<LengthLimitError>() {
+            return true;
+        }
+        source = err.source();
+    }
+    false
+}
+
+/// Turn a submission body into a `check` payload based on its content type.
+///
+/// `application/json` becomes Rune objects, `multipart/form-data` becomes an object of
+/// fields with uploaded files written into the sandbox, and anything else stays text.
+pub async fn read_submission(
+    request: Request,
+    sandbox: &SandboxHandle,
+    config: &SubmissionConfig,
+) -> Result<UserInput> {
+    let content_type = media_type(&request);
+
+    if let Some(allowed) = &config.content_types {
+        if !allowed
+            .iter()
+            .any(|a| a.eq_ignore_ascii_case(&content_type))
+        {
+            return Err(SubmissionError::UnsupportedContentType {
+                content_type,
+                allowed: allowed.clone(),
+            }
+            .into());
+        }
+    }
+
+    let (head, body) = read_body(request, config.max_body_bytes).await?;
+
+    if content_type == "application/json" {
+        let value = serde_json::from_slice(&body)
+            .map_err(|e| invalid_body(format!("Invalid JSON: {}", e)))?;
+        return Ok(UserInput::Structured(value));
+    }
+
+    if content_type == "multipart/form-data" {
+        let (parts, ()) = head.into_parts();
+        let request = Request::from_parts(parts, Body::from(body));
+        let multipart = Multipart::from_request(request, &())
+            .await
+            .map_err(|e| invalid_body(format!("Invalid multipart body: {}", e)))?;
+        return read_multipart(multipart, sandbox).await;
+    }
+
+    match String::from_utf8(body.to_vec()) {
+        Ok(text) => Ok(UserInput::Text(text)),
+        Err(e) => match config.invalid_utf8 {
+            InvalidUtf8::Bytes => Ok(UserInput::Bytes(e.into_bytes())),
+            InvalidUtf8::Reject => Err(SubmissionError::InvalidUtf8 {
+                valid_up_to: e.utf8_error().valid_up_to(),
+            }
+            .into()),
+        },
+    }
+}
+
+async fn read_multipart(mut multipart: Multipart, sandbox: &SandboxHandle) -> Result<UserInput> {
+    let mut fields = Map::new();
+    let mut uploads = 0;
+
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| invalid_body(format!("Invalid multipart field: {}", e)))?
+    {
+        let name = field.name().unwrap_or_default().to_string();
+
+        let value = match field.file_name().map(str::to_string) {
+            Some(filename) => {
+                let content_type = field.content_type().map(str::to_string);
+                let bytes = field.bytes().await.map_err(|e| {
+                    invalid_body(format!("Failed to read upload {}: {}", filename, e))
+                })?;
+
+                // Prefix with a counter so identical or hostile names can't collide
+                uploads += 1;
+                let stored = format!("{}-{}", uploads, safe_file_name(&filename));
+                store_upload(sandbox, &stored, &bytes).await?;
+
+                serde_json::json!({
+                    "filename": filename,
+                    "path": format!("{}/{}", UPLOAD_DIR, stored),
+                    "size": bytes.len(),
+                    "content_type": content_type,
+                })
+            }
+            None => Value::String(
+                field
+                    .text()
+                    .await
+                    .map_err(|e| invalid_body(format!("Invalid form field {}: {}", name, e)))?,
+            ),
+        };
+
+        // Repeated field names collect into an array
+        match fields.get_mut(&name) {
+            Some(Value::Array(values)) => values.push(value),
+            Some(existing) => *existing = Value::Array(vec![existing.take(), value]),
+            None => {
+                fields.insert(name, value);
+            }
+        }
+    }
+
+    Ok(UserInput::Structured(Value::Object(fields)))
+}
+
+/// Write an upload into the sandbox within its quota and the disk cap, measuring the
+/// sandbox off the async runtime
+async fn store_upload(sandbox: &SandboxHandle, stored: &str, bytes: &Bytes) -> Result<()> {
+    let sandbox = sandbox.clone();
+    let name = format!("{}/{}", UPLOAD_DIR, stored);
+    let bytes = bytes.clone();
+    let written = tokio::task::spawn_blocking(move || {
+        std::fs::create_dir_all(sandbox.path.join(UPLOAD_DIR))?;
+        sandbox.write(&name, &bytes)
+    })
+    .await?;
+
+    match written {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == io::ErrorKind::QuotaExceeded => {
+            metrics::LIMIT_KILLS
+                .with_label_values(&["disk_quota"])
+                .inc();
+            Err(SubmissionError::UploadTooLarge {
+                reason: err.to_string(),
+            }
+            .into())
+        }
+        // Over the disk cap, surfaced as the `ServerBusy` inside
+        Err(err) if err.get_ref().is_some_and(|inner| inner.is::<ServerBusy>()) => {
+            let busy = err.into_inner().unwrap().downcast::<ServerBusy>().unwrap();
+            Err((*busy).into())
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Keep only the final path component, limited to a conservative character set
+fn safe_file_name(filename: &str) -> String {
+    let base = filename.rsplit(['/', '\\']).next().unwrap_or("");
+    let cleaned: String = base
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    match cleaned.trim_start_matches('.') {
+        "" => "upload".to_string(),
+        name => name.to_string(),
+    }
+}
@@ -0,0 +1,166 @@
+use std::ops::Range;
+
+/// Placeholder replaced by the player's payload
+pub const USER_INPUT_PLACEHOLDER: &str = "user_input";
+
+/// A template with the payload spliced in, remembering which bytes came from the player
+#[derive(Clone, Debug, Default)]
+pub struct Rendered {
+    pub source: String,
+    /// Byte ranges of `source` holding the payload, in order
+    pub player: Vec<Range<usize>>,
+}
+
+impl Rendered {
+    /// Source the script put together itself. It may well embed template text, so none
+    /// of it counts as the player's: quoted source is masked whole and no diagnostic is
+    /// located in a payload.
+    pub fn plain(source: &str) -> Self {
+        Self {
+            source: source.to_string(),
+            player: Vec::new(),
+        }
+    }
+
+    /// Whether the byte at `offset` of the rendered source came from the payload
+    pub fn is_player(&self, offset: usize) -> bool {
+        self.player.iter().any(|range| range.contains(&offset))
+    }
+
+    /// The payload as the player wrote it
+    pub fn payload(&self) -> &str {
+        self.player
+            .first()
+            .map(|range| &self.source[range.clone()])
+            .unwrap_or_default()
+    }
+
+    /// Map a 1-based line and byte column of the rendered source to the payload, `None`
+    /// when it points into the template
+    pub fn payload_position(&self, line: usize, column: usize) -> Option<PayloadPosition> {
+        let line_start = self
+            .source
+            .split_inclusive('\n')
+            .take(line.checked_sub(1)?)
+            .map(str::len)
+            .sum::<usize>();
+        let mut rendered = line_start + column.checked_sub(1)?;
+        // Columns should fall on characters, but never slice through one if they don't
+        while !self.source.is_char_boundary(rendered) {
+            rendered -= 1;
+        }
+
+        // A diagnostic just past the payload, such as a missing `;`, still belongs to it
+        let range = self
+            .player
+            .iter()
+            .find(|range| range.contains(&rendered) || range.end == rendered)?;
+        let offset = rendered - range.start;
+
+        let before = &self.source[range.start..rendered];
+        Some(PayloadPosition {
+            offset,
+            line: before.matches('\n').count() + 1,
+            column: offset - before.rfind('\n').map_or(0, |i| i + 1) + 1,
+        })
+    }
+}
+
+/// Location inside the payload, lines and columns 1-based
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PayloadPosition {
+    /// Byte offset into the payload
+    pub offset: usize,
+    pub line: usize,
+    /// In bytes, like the compiler's
+    pub column: usize,
+}
+
+/// Replace every `${{user_input}}` in `template` with `user_input`. Other placeholders are
+/// left as they are, which `jailbox lint` warns about.
+pub fn render(template: &str, user_input: &str) -> Rendered {
+    let mut rendered = Rendered::default();
+    let mut rest = template;
+
+    while let Some(start) = rest.find("${{") {
+        let body = &rest[start + 3..];
+        let Some(len) = body.find("}}") else {
+            break;
+        };
+        rendered.source.push_str(&rest[..start]);
+        if body[..len].trim() == USER_INPUT_PLACEHOLDER {
+            let offset = rendered.source.len();
+            rendered.source.push_str(user_input);
+            rendered.player.push(offset..rendered.source.len());
+        } else {
+            rendered.source.push_str(&rest[start..start + 3 + len + 2]);
+        }
+        rest = &body[len + 2..];
+    }
+    rendered.source.push_str(rest);
+
+    rendered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position(offset: usize, line: usize, column: usize) -> Option<PayloadPosition> {
+        Some(PayloadPosition {
+            offset,
+            line,
+            column,
+        })
+    }
+
+    #[test]
+    fn render_marks_player_bytes() {
+        let rendered = render("int a;\nvoid f() { ${{ user_input }} }\n${{other}}", "x\ny");
+        assert_eq!(rendered.source, "int a;\nvoid f() { x\ny }\n${{other}}");
+        assert_eq!(rendered.player.len(), 1);
+        assert_eq!(rendered.player[0], 18..21);
+        assert_eq!(rendered.payload(), "x\ny");
+        assert!(!rendered.is_player(17));
+        assert!(rendered.is_player(18) && rendered.is_player(20));
+        assert!(!rendered.is_player(21));
+    }
+
+    #[test]
+    fn positions_inside_the_payload() {
+        let rendered = render("int a;\nvoid f() { ${{user_input}} }", "x;\n  y");
+        assert_eq!(rendered.payload_position(2, 12), position(0, 1, 1));
+        assert_eq!(rendered.payload_position(2, 13), position(1, 1, 2));
+        assert_eq!(rendered.payload_position(3, 3), position(5, 2, 3));
+    }
+
+    #[test]
+    fn positions_at_the_template_boundary() {
+        let rendered = render("int a;\nvoid f() { ${{user_input}} }", "x");
+        // Just before the payload is the template's
+        assert_eq!(rendered.payload_position(2, 11), None);
+        // Just past it is the payload's, like a missing `;` after it
+        assert_eq!(rendered.payload_position(2, 13), position(1, 1, 2));
+        assert_eq!(rendered.payload_position(2, 14), None);
+        assert_eq!(rendered.payload_position(1, 1), None);
+        assert_eq!(rendered.payload_position(0, 1), None);
+        assert_eq!(rendered.payload_position(9, 1), None);
+    }
+
+    #[test]
+    fn positions_in_later_placeholders() {
+        let rendered = render("${{user_input}}\nint b;\n${{user_input}}", "a\nbc");
+        assert_eq!(rendered.payload_position(2, 2), position(3, 2, 2));
+        assert_eq!(rendered.payload_position(3, 1), None);
+        assert_eq!(rendered.payload_position(4, 1), position(0, 1, 1));
+        assert_eq!(rendered.payload_position(5, 2), position(3, 2, 2));
+    }
+
+    #[test]
+    fn positions_never_split_characters() {
+        let rendered = render("f(${{user_input}})", "éé   €é;ab;  éé");
+        // Byte 9 of the source is inside `€`
+        assert_eq!(rendered.payload_position(1, 12), position(7, 1, 8));
+        assert_eq!(rendered.payload_position(1, 9), position(6, 1, 7));
+    }
+}